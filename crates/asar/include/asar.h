@@ -93,7 +93,13 @@ typedef enum asar_status {
   glob,
   parse_int,
   io,
-  json
+  json,
+  extraction,
+  integrity_mismatch,
+  out_of_range,
+  path_traversal,
+  link_loop,
+  transform
 } asar_status;
 
 ASAR_API(asar_status) asar_list_package(const char* archive,
@@ -1,9 +1,9 @@
 use std::{
+  borrow::Cow,
   collections::HashMap,
   ffi::OsStr,
   fs::{File, Metadata},
-  io::Seek,
-  io::{Read, SeekFrom, Write},
+  io::{Read, Seek, SeekFrom, Write},
   path::{Path, PathBuf},
 };
 
@@ -11,14 +11,22 @@ use std::{
 use std::os::unix::prelude::MetadataExt;
 
 use crate::{
+  check::{check_filesystem, Finding},
   crawlfs::{crawl_filesystem, determine_file_type},
-  disk::{read_archive_header, FileItem},
+  disk::{read_header, ArchiveWriter, OffsetWriter},
+  entry::EntryReader,
   error::{Error, ErrorKind, Result},
-  filesystem::{get_dir, Filesystem},
-  integrity::{get_file_integrity, BUFFER_SIZE},
-  node::{DirectoryNode, FileNode, LinkNode, Node},
+  filesystem::{get_dir, is_valid_entry_name, Filesystem},
+  integrity::{
+    copy_with_integrity, get_block_hash, get_integrity, placeholder_integrity, IntegrityReader,
+    BUFFER_SIZE,
+  },
+  node::{DirectoryNode, FileNode, HeaderIntegrity, Integrity, LinkNode, Node},
+  source::{ReadAt, SeekSource, UnpackedDir, UnpackedProvider},
+  transform::{Applied, BuiltinTransform, TransformContext, TransformHook},
 };
 use glob::MatchOptions;
+use memmap2::Mmap;
 use path_absolutize::*;
 
 #[cfg(not(target_os = "windows"))]
@@ -50,73 +58,377 @@ pub struct ListOptions {
   pub is_pack: bool,
 }
 
+impl Default for ListOptions {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
 impl ListOptions {
   pub fn new() -> Self {
     ListOptions { is_pack: false }
   }
 }
 
+#[derive(Clone)]
+pub struct ExtractOptions {
+  /// Number of files extracted at once, or 0 for one per available CPU.
+  pub threads: usize,
+}
+
+impl Default for ExtractOptions {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl ExtractOptions {
+  pub fn new() -> Self {
+    ExtractOptions { threads: 1 }
+  }
+}
+
+/// A packed or unpacked file whose content does not match the `integrity`
+/// recorded in the archive header.
+#[derive(Debug, Clone)]
+pub struct IntegrityMismatch {
+  pub path: String,
+  /// Empty when the header has no `integrity` for the file.
+  pub expected: String,
+  /// Empty when the content could not be checked.
+  pub actual: String,
+  /// Why the content could not be checked, if it could not.
+  pub error: Option<String>,
+}
+
 pub struct AsarFile {
-  fd: File,
+  fd: Box<dyn ReadAt>,
+  unpacked: Option<Box<dyn UnpackedProvider>>,
   filesystem: Filesystem,
+  base_offset: u64,
+  data_size: u64,
+  verify_integrity: bool,
 }
 
 impl AsarFile {
   pub fn open<T: AsRef<Path>>(archive: T) -> Result<Self> {
-    let (_, header, header_size, fd) = read_archive_header(&archive)?;
-    let mut filesystem = Filesystem::new(archive.as_ref().absolutize()?.to_path_buf());
+    Self::open_at(archive, 0, None)
+  }
+
+  /// Opens an archive embedded `base_offset` bytes into a larger file, such
+  /// as a payload appended to a launcher binary. `len` bounds the archive if
+  /// other data follows it.
+  ///
+  /// Unpacked files are still looked up in `<archive>.unpacked`.
+  pub fn open_at<T: AsRef<Path>>(archive: T, base_offset: u64, len: Option<u64>) -> Result<Self> {
+    let fd = File::open(&archive)?;
+    Self::from_file(archive.as_ref(), Box::new(fd), base_offset, len)
+  }
+
+  /// Opens an archive through a read-only memory mapping, so that
+  /// `read_file_mapped` can hand out packed files without copying them.
+  ///
+  /// # Safety
+  ///
+  /// The archive must not be modified or truncated, by this or any other
+  /// process, for as long as the returned `AsarFile` lives. Borrowed reads
+  /// would see the changes, and reading a truncated part of the mapping
+  /// kills the process with `SIGBUS`.
+  pub unsafe fn open_mmap<T: AsRef<Path>>(archive: T) -> Result<Self> {
+    let fd = File::open(&archive)?;
+    // Safety: the mapping is read-only and lives as long as `self`, and the
+    // caller promises that the file does not change meanwhile.
+    let map = unsafe { Mmap::map(&fd)? };
+    Self::from_file(archive.as_ref(), Box::new(map), 0, None)
+  }
+
+  fn from_file(
+    archive: &Path,
+    fd: Box<dyn ReadAt>,
+    base_offset: u64,
+    len: Option<u64>,
+  ) -> Result<Self> {
+    let mut asar = Self::from_source(fd, base_offset, len)?;
+    asar.filesystem.src = archive.absolutize()?.to_path_buf();
+    let unpacked_dir = asar.filesystem.src.to_string_lossy().to_string() + ".unpacked";
+    asar.unpacked = Some(Box::new(UnpackedDir::new(unpacked_dir)));
+    Ok(asar)
+  }
+
+  /// Opens an archive held in memory.
+  pub fn from_bytes<B: Into<Vec<u8>>>(bytes: B) -> Result<Self> {
+    Self::from_source(Box::new(bytes.into()), 0, None)
+  }
+
+  /// Opens an archive from any seekable source. Unpacked files can not be
+  /// read until a provider is set with `set_unpacked_provider`.
+  ///
+  /// Reads from `reader` are serialized, unlike reads from a file or memory.
+  pub fn from_reader<R: Read + Seek + Send + 'static>(reader: R) -> Result<Self> {
+    Self::from_source(Box::new(SeekSource::new(reader)), 0, None)
+  }
+
+  fn from_source(fd: Box<dyn ReadAt>, base_offset: u64, len: Option<u64>) -> Result<Self> {
+    let mut reader = EntryReader::packed(fd.as_ref(), 0, fd.len()?);
+    let (_, header, header_size, len) = read_header(&mut reader, base_offset, len)?;
+    let mut filesystem = Filesystem::new(PathBuf::new());
     filesystem.header = header;
     filesystem.header_size = header_size as u64;
-    Ok(AsarFile { fd, filesystem })
+    Ok(AsarFile {
+      fd,
+      unpacked: None,
+      data_size: len.saturating_sub(8 + filesystem.header_size),
+      filesystem,
+      base_offset,
+      verify_integrity: false,
+    })
+  }
+
+  /// Sets where unpacked files are read from, replacing the `.unpacked`
+  /// directory next to an archive opened from a path.
+  pub fn set_unpacked_provider<P: UnpackedProvider + 'static>(&mut self, provider: P) {
+    self.unpacked = Some(Box::new(provider));
+  }
+
+  /// When enabled, `read_file` and `extract_file` fail with
+  /// `ErrorKind::IntegrityMismatch` if a file does not match its header hash.
+  pub fn set_verify_integrity(&mut self, verify: bool) {
+    self.verify_integrity = verify;
   }
 
-  pub fn stat_file(&mut self, p: &str, follow_links: Option<bool>) -> Result<&Node> {
+  pub fn stat_file(&self, p: &str, follow_links: Option<bool>) -> Result<&Node> {
     let info: &Node = self.filesystem.get_file(p, follow_links)?;
     Ok(info)
   }
 
-  pub fn read_file(&mut self, filename: &str) -> Result<Vec<u8>> {
-    let info = self.filesystem.get_file(&filename, None)?;
+  pub fn read_file(&self, filename: &str) -> Result<Vec<u8>> {
+    let (path, node) = self.get_file_node(filename)?;
+    let mut entry = self.open_file_node(&path, &node)?;
+    let mut buffer = Vec::with_capacity(entry.size() as usize);
+    entry.read_to_end(&mut buffer)?;
+    if self.verify_integrity {
+      check_integrity(filename, &node, &get_integrity(&buffer[..])?)?;
+    }
+    Ok(buffer)
+  }
 
-    match info {
-      Node::Directory(_) | Node::Link(_) => {
-        return Err(Error::new(ErrorKind::ExpectFileNode(filename.to_owned())));
-      }
-      Node::File(FileNode {
-        offset,
-        size,
-        unpacked,
-        ..
-      }) => {
-        let info_size = *size;
-        let mut buffer: Vec<u8>;
-        if info_size <= 0 {
-          return Ok(vec![]);
+  /// Reads `filename`, borrowing packed files from the mapping of an archive
+  /// opened with `open_mmap` or from the bytes given to `from_bytes`.
+  /// Unpacked files, and every file of an archive read through the file
+  /// system or a reader, are read into an owned buffer instead.
+  pub fn read_file_mapped(&self, filename: &str) -> Result<Cow<'_, [u8]>> {
+    let (path, node) = self.get_file_node(filename)?;
+    let data: Cow<'_, [u8]> = if node.unpacked.unwrap_or(false) {
+      let (mut fd, size) = self.unpacked_provider(&path)?.open(&path)?;
+      let mut buffer = Vec::with_capacity(size as usize);
+      fd.read_to_end(&mut buffer)?;
+      Cow::Owned(buffer)
+    } else {
+      let (start, size) = self.packed_range(&path, &node)?;
+      match self.fd.as_slice() {
+        Some(data) => Cow::Borrowed(&data[start as usize..(start + size) as usize]),
+        None => {
+          let mut buffer = Vec::with_capacity(size as usize);
+          EntryReader::packed(self.fd.as_ref(), start, size).read_to_end(&mut buffer)?;
+          Cow::Owned(buffer)
         }
+      }
+    };
+    if self.verify_integrity {
+      check_integrity(filename, &node, &get_integrity(&data[..])?)?;
+    }
+    Ok(data)
+  }
 
-        let info_unpacked = unpacked.unwrap_or(false);
+  /// Returns the canonical path of `filename` inside the archive, with every
+  /// link along the way resolved.
+  pub fn realpath(&self, filename: &str) -> Result<String> {
+    self.filesystem.realpath(filename)
+  }
 
-        if info_unpacked {
-          let unpacked_dir =
-            self.filesystem.src.to_string_lossy().as_ref().to_owned() + &".unpacked";
-          let target_path = PathBuf::from(unpacked_dir).join(&filename);
-          // it's an unpacked file, copy it.
-          buffer = std::fs::read(target_path)?
-        } else {
-          buffer = vec![0; info_size];
-          let info_offset = u64::from_str_radix(
-            &offset
-              .as_ref()
-              .ok_or_else(|| Error::new(ErrorKind::UnknownOffset(filename.to_owned())))?,
-            10,
-          )?;
-          let offset = 8u64 + self.filesystem.header_size + info_offset;
-          self.fd.seek(SeekFrom::Start(offset))?;
-          self.fd.read(&mut buffer)?;
-        }
+  /// Opens a streaming reader over the content of `filename`, following links.
+  pub fn open_entry(&self, filename: &str) -> Result<EntryReader<'_>> {
+    let (path, node) = self.get_file_node(filename)?;
+    self.open_file_node(&path, &node)
+  }
+
+  /// Reads `len` bytes of `filename` starting at `start`.
+  ///
+  /// In verified mode only the integrity blocks overlapping the range are
+  /// read and hashed, instead of the whole file.
+  pub fn read_range(&self, filename: &str, start: u64, len: u64) -> Result<Vec<u8>> {
+    let (path, node) = self.get_file_node(filename)?;
+    let end = start
+      .checked_add(len)
+      .filter(|end| *end <= node.size as u64)
+      .ok_or_else(|| Error::new(ErrorKind::OutOfRange(filename.to_owned())))?;
+    if len == 0 {
+      return Ok(vec![]);
+    }
+
+    let integrity = match &node.integrity {
+      Some(integrity) if self.verify_integrity => integrity.clone(),
+      // a file without hashes can not pass for verified
+      None if self.verify_integrity => {
+        return Err(Error::new(ErrorKind::IntegrityMismatch(
+          filename.to_owned(),
+        )));
+      }
+      _ => {
+        let mut entry = self.open_file_node(&path, &node)?;
+        let mut buffer = vec![0; len as usize];
+        entry.seek(SeekFrom::Start(start))?;
+        entry.read_exact(&mut buffer)?;
         return Ok(buffer);
       }
     };
+
+    let block_size = integrity.block_size as u64;
+    if block_size == 0 {
+      return Err(Error::new(ErrorKind::IntegrityMismatch(
+        filename.to_owned(),
+      )));
+    }
+    let first_block = start / block_size;
+    let last_block = (end - 1) / block_size;
+    let block_start = first_block * block_size;
+    let block_end = std::cmp::min((last_block + 1) * block_size, node.size as u64);
+
+    let mut entry = self.open_file_node(&path, &node)?;
+    let mut buffer = vec![0; (block_end - block_start) as usize];
+    entry.seek(SeekFrom::Start(block_start))?;
+    entry.read_exact(&mut buffer)?;
+
+    for (index, block) in buffer.chunks(block_size as usize).enumerate() {
+      let expected = integrity.blocks.get(first_block as usize + index);
+      if expected != Some(&get_block_hash(block)) {
+        return Err(Error::new(ErrorKind::IntegrityMismatch(
+          filename.to_owned(),
+        )));
+      }
+    }
+
+    Ok(buffer[(start - block_start) as usize..(end - block_start) as usize].to_vec())
+  }
+
+  /// Checks every packed and unpacked file against the `integrity` recorded
+  /// in the header and returns the files that do not match. Files without
+  /// `integrity`, or that can not be read, are reported with an `error`.
+  pub fn verify(&self) -> Result<Vec<IntegrityMismatch>> {
+    let mut mismatches: Vec<IntegrityMismatch> = vec![];
+    self.verify_node("", &self.filesystem.header, true, &mut mismatches);
+    Ok(mismatches)
+  }
+
+  fn verify_node(
+    &self,
+    path: &str,
+    node: &Node,
+    valid_path: bool,
+    mismatches: &mut Vec<IntegrityMismatch>,
+  ) {
+    let node = match node {
+      Node::Directory(DirectoryNode { files, .. }) => {
+        for (name, child) in files {
+          let child_path = if path.is_empty() {
+            name.clone()
+          } else {
+            path.to_owned() + "/" + name
+          };
+          let valid_path = valid_path && is_valid_entry_name(name);
+          self.verify_node(&child_path, child, valid_path, mismatches);
+        }
+        return;
+      }
+      Node::File(node) => node,
+      Node::Link(_) => return,
+    };
+
+    let mut mismatch = IntegrityMismatch {
+      path: path.to_owned(),
+      expected: String::new(),
+      actual: String::new(),
+      error: None,
+    };
+    let Some(expected) = &node.integrity else {
+      mismatch.error = Some("missing integrity".to_owned());
+      mismatches.push(mismatch);
+      return;
+    };
+    mismatch.expected = expected.hash.clone();
+    if !valid_path {
+      // never looked up, so that an unpacked file can not be read from
+      // outside of `.unpacked`
+      mismatch.error = Some("illegal entry name".to_owned());
+      mismatches.push(mismatch);
+      return;
+    }
+    match self.open_file_node(path, node).and_then(get_integrity) {
+      Ok(actual) if crate::integrity::matches(expected, &actual) => {}
+      Ok(actual) => {
+        mismatch.actual = actual.hash;
+        mismatches.push(mismatch);
+      }
+      // a missing or truncated file is a failure of that file only
+      Err(err) => {
+        mismatch.error = Some(err.to_string());
+        mismatches.push(mismatch);
+      }
+    }
+  }
+
+  /// Audits the structure of the archive without extracting it, and returns
+  /// every problem found.
+  pub fn check(&self) -> Vec<Finding> {
+    check_filesystem(&self.filesystem, self.data_size, self.unpacked.as_deref())
+  }
+
+  pub(crate) fn get_file_node(&self, filename: &str) -> Result<(String, FileNode)> {
+    match self.filesystem.resolve(filename, true)? {
+      (path, Node::File(node)) => Ok((path, node.clone())),
+      _ => Err(Error::new(ErrorKind::ExpectFileNode(filename.to_owned()))),
+    }
+  }
+
+  fn unpacked_provider(&self, filename: &str) -> Result<&dyn UnpackedProvider> {
+    match &self.unpacked {
+      Some(provider) => Ok(provider.as_ref()),
+      None => Err(
+        std::io::Error::new(
+          std::io::ErrorKind::NotFound,
+          format!("{}: no provider for unpacked files", filename),
+        )
+        .into(),
+      ),
+    }
+  }
+
+  pub(crate) fn open_file_node(&self, filename: &str, node: &FileNode) -> Result<EntryReader<'_>> {
+    if node.unpacked.unwrap_or(false) {
+      let (fd, size) = self.unpacked_provider(filename)?.open(filename)?;
+      return Ok(EntryReader::unpacked(fd, size));
+    }
+    let (start, size) = self.packed_range(filename, node)?;
+    Ok(EntryReader::packed(self.fd.as_ref(), start, size))
+  }
+
+  /// Returns where the content of a packed file starts in the source, and
+  /// its size.
+  pub(crate) fn packed_range(&self, filename: &str, node: &FileNode) -> Result<(u64, u64)> {
+    let size = node.size as u64;
+    if size == 0 {
+      return Ok((0, 0));
+    }
+    let info_offset: u64 = node
+      .offset
+      .as_ref()
+      .ok_or_else(|| Error::new(ErrorKind::UnknownOffset(filename.to_owned())))?
+      .parse()?;
+    if !matches!(info_offset.checked_add(size), Some(end) if end <= self.data_size) {
+      return Err(Error::new(ErrorKind::OutOfRange(filename.to_owned())));
+    }
+    let start = self.base_offset + 8 + self.filesystem.header_size + info_offset;
+    Ok((start, size))
   }
 
   pub fn list(&self, options: &ListOptions) -> Result<Vec<String>> {
@@ -124,124 +436,128 @@ impl AsarFile {
   }
 
   fn extract_file_node<T: AsRef<Path>>(
-    &mut self,
+    &self,
     filename: &str,
     node: FileNode,
     dest: T,
   ) -> Result<()> {
-    let FileNode {
-      offset,
-      size,
-      unpacked,
-      executable,
-      ..
-    } = node;
-    let info_size = size;
-    let info_unpacked = unpacked.unwrap_or(false);
-    if info_unpacked {
-      let unpacked_dir = self.filesystem.src.to_string_lossy().as_ref().to_owned() + &".unpacked";
-      let target_path = PathBuf::from(unpacked_dir).join(&filename);
-      // it's an unpacked file, copy it.
+    let unpacked = node.unpacked.unwrap_or(false);
+    if unpacked {
       std::fs::create_dir_all(crate::filesystem::get_dir(&dest))?;
-      std::fs::copy(target_path, &dest)?;
+    }
+    if unpacked && !self.verify_integrity {
+      // it's an unpacked file, copy it.
+      self
+        .unpacked_provider(filename)?
+        .copy_to(filename, dest.as_ref())?;
     } else {
-      let info_offset = u64::from_str_radix(
-        &offset
-          .as_ref()
-          .ok_or_else(|| Error::new(ErrorKind::UnknownOffset(filename.to_owned())))?,
-        10,
-      )?;
-      let offset = 8u64 + self.filesystem.header_size + info_offset;
-
-      let mut left = info_size;
-      self.fd.seek(std::io::SeekFrom::Start(offset))?;
       let mut options = std::fs::OpenOptions::new();
-      options.create(true).write(true);
-      if executable.unwrap_or(false) {
+      options.create(true).write(true).truncate(true);
+      if node.executable.unwrap_or(false) {
         #[cfg(not(target_os = "windows"))]
         options.mode(0o755);
       }
-      let mut buffer = vec![0; BUFFER_SIZE];
       let mut dest_fd = options.open(&dest)?;
-      while left > 0 {
-        let read_size = self.fd.read(&mut buffer)?;
-        assert!(read_size > 0, "failed to read file: {}", filename);
-        if read_size > left {
-          dest_fd.write(&buffer[0..left])?;
-          break;
-        } else {
-          dest_fd.write(&buffer[0..read_size])?;
-          left -= read_size;
-        };
+      if self.verify_integrity {
+        let mut entry = IntegrityReader::new(self.open_file_node(filename, &node)?);
+        std::io::copy(&mut entry, &mut dest_fd)?;
+        if let Err(e) = check_integrity(filename, &node, &entry.finalize()) {
+          drop(dest_fd);
+          let _ = std::fs::remove_file(&dest);
+          return Err(e);
+        }
+        // like the plain copy of an unpacked file does
+        if unpacked {
+          if let Some(permissions) = self.unpacked_provider(filename)?.permissions(filename) {
+            dest_fd.set_permissions(permissions)?;
+          }
+        }
+      } else {
+        self.copy_file_node(filename, &node, &mut dest_fd)?;
       }
     }
 
     Ok(())
   }
 
-  pub fn extract_file<T: AsRef<Path>>(&mut self, filename: &str, dest: T) -> Result<()> {
-    let mut link_target: Option<String> = None;
-    {
-      let file = self.filesystem.get_file(filename, Some(FOLLOW_LINKS))?;
-
-      match file {
-        Node::Directory(..) => {
-          return Err(Error::new(ErrorKind::ExpectFileNode(filename.to_owned())));
-        }
-        Node::Link(LinkNode { link }) => {
-          link_target = Some(link.clone());
-        }
-        Node::File(node) => {
-          self.extract_file_node(filename, node.clone(), dest.as_ref())?;
-        }
-      };
+  fn copy_file_node(&self, filename: &str, node: &FileNode, dest: &mut File) -> Result<u64> {
+    let mut entry = self.open_file_node(filename, node)?;
+    #[cfg(target_os = "linux")]
+    if let (false, Some(src)) = (node.unpacked.unwrap_or(false), self.fd.as_file()) {
+      let (start, size) = self.packed_range(filename, node)?;
+      return Ok(crate::copy::copy_resuming(&mut entry, dest, |dest| {
+        crate::copy::copy_file_range(src, start, size, dest)
+      })?);
     }
+    Ok(std::io::copy(&mut entry, dest)?)
+  }
 
-    if let Some(link) = link_target {
-      self.extract_file(&link, dest.as_ref())?;
-    }
+  pub fn extract_file<T: AsRef<Path>>(&self, filename: &str, dest: T) -> Result<()> {
+    let (path, node) = self.get_file_node(filename)?;
+    self.extract_file_node(&path, node, dest.as_ref())
+  }
 
-    Ok(())
+  pub fn extract_all<T: AsRef<Path>>(&self, dest: T) -> Result<()> {
+    self.extract_all_with_options(dest, &ExtractOptions::new())
   }
 
-  pub fn extract_all<T: AsRef<Path>>(&mut self, dest: T) -> Result<()> {
+  /// Extracts every entry: directories first, then files on
+  /// `options.threads` threads, and links last.
+  pub fn extract_all_with_options<T: AsRef<Path>>(
+    &self,
+    dest: T,
+    options: &ExtractOptions,
+  ) -> Result<()> {
+    // refuse to write anything if an entry would land outside of `dest`
+    self.filesystem.validate_paths()?;
+
     // create destination directory
     let filenames = self.list(&ListOptions::new())?;
-    std::fs::create_dir_all(&dest)?;
+    let dest = dest.as_ref();
+    std::fs::create_dir_all(dest)?;
 
-    let mut extraction_erros: Vec<Error> = Vec::new();
+    let mut files: Vec<(String, &FileNode, PathBuf)> = vec![];
+    let mut links: Vec<(&str, PathBuf)> = vec![];
     for full_path in filenames.iter() {
       // Remove leading slash
       let filename = &full_path[1..];
-      let dest_ref = dest.as_ref();
-      let dest_filename = dest_ref.join(filename);
-      let file = self.filesystem.get_file(filename, Some(FOLLOW_LINKS))?;
+      let dest_filename = dest.join(filename);
+      let (path, file) = if FOLLOW_LINKS {
+        self.filesystem.resolve(filename, true)?
+      } else {
+        (filename.to_owned(), self.filesystem.get_node(filename)?)
+      };
 
       match file {
         Node::Directory(..) => {
           // it's a directory, create it and continue with the next entry
           std::fs::create_dir_all(&dest_filename)?;
         }
-        Node::Link(LinkNode { link }) => {
-          let link_src_path = dest_ref.join(link);
-          let link_src_path = crate::filesystem::get_dir(link_src_path);
-          let link_dest_path = crate::filesystem::get_dir(&dest_filename);
-          let relative_path = crate::filesystem::relative(&link_dest_path, &link_src_path)?;
-          // try to delete output file, because we can't overwrite a link
-          let _ = std::fs::remove_file(&dest_filename);
-          let link_to =
-            relative_path.join(PathBuf::from(link).file_name().unwrap_or(&OsStr::new("..")));
-          symlink(link_to, &dest_filename)?;
-        }
-        Node::File(node) => {
-          if let Err(e) = self.extract_file_node(filename, node.clone(), dest_filename.as_path()) {
-            extraction_erros.push(e);
-          }
-        }
+        Node::Link(LinkNode { link }) => links.push((link, dest_filename)),
+        Node::File(node) => files.push((path, node, dest_filename)),
       };
     }
 
-    if extraction_erros.len() > 0 {
+    let extraction_erros: Vec<Error> =
+      crate::parallel::map(&files, options.threads, |(path, node, dest_filename)| {
+        self.extract_file_node(path, (*node).clone(), dest_filename)
+      })
+      .into_iter()
+      .filter_map(|result| result.err())
+      .collect();
+
+    for (link, dest_filename) in links {
+      let link_src_path = dest.join(link);
+      let link_src_path = crate::filesystem::get_dir(link_src_path);
+      let link_dest_path = crate::filesystem::get_dir(&dest_filename);
+      let relative_path = crate::filesystem::relative(&link_dest_path, &link_src_path)?;
+      // try to delete output file, because we can't overwrite a link
+      let _ = std::fs::remove_file(&dest_filename);
+      let link_to = relative_path.join(PathBuf::from(link).file_name().unwrap_or(OsStr::new("..")));
+      symlink(link_to, &dest_filename)?;
+    }
+
+    if !extraction_erros.is_empty() {
       return Err(Error::new(ErrorKind::Extraction(extraction_erros)));
     }
 
@@ -249,18 +565,43 @@ impl AsarFile {
   }
 }
 
+/// Fails unless `node` has an integrity and `actual` matches it.
+fn check_integrity(filename: &str, node: &FileNode, actual: &Integrity) -> Result<()> {
+  match &node.integrity {
+    Some(expected) if crate::integrity::matches(expected, actual) => Ok(()),
+    _ => Err(Error::new(ErrorKind::IntegrityMismatch(
+      filename.to_owned(),
+    ))),
+  }
+}
+
 pub struct CreateOptions {
   pub pattern: String,
   pub dot: Option<bool>,
   pub ordering: Option<PathBuf>,
   pub unpack_dir: Option<String>,
   pub unpack: Option<String>,
-  pub transform: Option<fn(&str) -> Option<Box<dyn Transform>>>,
+  pub transform: Option<TransformFn>,
+  /// Ready-made transforms, applied in order after `transform`.
+  pub builtins: Vec<BuiltinTransform>,
+  /// Rewrite files as they are packed, applied in order after `builtins`.
+  pub transforms: Vec<TransformHook>,
+  /// Number of files hashed and transformed at once, or 0 for one per
+  /// available CPU.
+  pub threads: usize,
 }
 
+pub type TransformFn = fn(&str) -> Option<Box<dyn Transform>>;
+
 pub trait Transform: Read + Write {
   fn transform(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-    Ok(self.write(buf)?)
+    self.write(buf)
+  }
+}
+
+impl Default for CreateOptions {
+  fn default() -> Self {
+    Self::new()
   }
 }
 
@@ -273,19 +614,23 @@ impl CreateOptions {
       unpack_dir: None,
       unpack: None,
       transform: None,
+      builtins: vec![],
+      transforms: vec![],
+      threads: 1,
     }
   }
 }
 
 pub fn create_package<S: AsRef<Path>, D: AsRef<Path>>(src: S, dest: D) -> Result<()> {
-  create_package_with_options(src, dest, &CreateOptions::new())
+  create_package_with_options(src, dest, &CreateOptions::new())?;
+  Ok(())
 }
 
 pub fn create_package_with_options<S: AsRef<Path>, D: AsRef<Path>>(
   src: S,
   dest: D,
   options: &CreateOptions,
-) -> Result<()> {
+) -> Result<HeaderIntegrity> {
   let (filenames, mut metadata) = crawl_filesystem(
     src.as_ref().to_string_lossy().to_string() + &options.pattern,
     MatchOptions {
@@ -304,7 +649,7 @@ pub fn create_package_from_files<S: AsRef<Path>, D: AsRef<Path>>(
   filenames: &Vec<String>,
   metadata: &mut HashMap<String, Metadata>,
   options: &CreateOptions,
-) -> Result<()> {
+) -> Result<HeaderIntegrity> {
   let src = src.as_ref().absolutize()?;
   let dest = dest.as_ref().absolutize()?;
 
@@ -316,7 +661,7 @@ pub fn create_package_from_files<S: AsRef<Path>, D: AsRef<Path>>(
     for line in std::fs::read_to_string(ordering)?.lines() {
       let mut l: &str = line;
       if l.contains(":") {
-        l = line.split(':').last().unwrap();
+        l = line.split(':').next_back().unwrap();
       }
       l = l.trim();
       if l.starts_with("/") {
@@ -362,7 +707,9 @@ pub fn create_package_from_files<S: AsRef<Path>, D: AsRef<Path>>(
 
   let mut unpack_dirs: Vec<String> = vec![];
 
-  let mut files: Vec<FileItem> = vec![];
+  std::fs::create_dir_all(get_dir(&dest))?;
+  let mut writer = ArchiveWriter::new(&dest)?;
+  let mut files: Vec<PackItem> = vec![];
 
   for filename in &filenames_sorted {
     if !metadata.contains_key(filename) {
@@ -376,7 +723,7 @@ pub fn create_package_from_files<S: AsRef<Path>, D: AsRef<Path>>(
       if let Some(unpack_dir) = &options.unpack_dir {
         let relative_path = crate::filesystem::relative(&src, Path::new(filename))?;
         should_unpack = is_unpacked_dir(
-          &relative_path.to_string_lossy().to_string(),
+          relative_path.to_string_lossy().as_ref(),
           unpack_dir,
           &mut unpack_dirs,
         )?;
@@ -394,100 +741,237 @@ pub fn create_package_from_files<S: AsRef<Path>, D: AsRef<Path>>(
       }
       if !should_unpack {
         if let Some(unpack_dir) = &options.unpack_dir {
-          let dirname = crate::filesystem::relative(&src, &get_dir(filename))?;
+          let dirname = crate::filesystem::relative(&src, get_dir(filename))?;
           should_unpack = is_unpacked_dir(
-            &dirname.to_string_lossy().to_string(),
+            dirname.to_string_lossy().as_ref(),
             unpack_dir,
             &mut unpack_dirs,
           )?;
         }
       }
 
-      let mut file_item = FileItem {
-        filename: filename.clone(),
-        unpack: should_unpack,
-        transformed_file: None,
-      };
-
       let dirpath = get_dir(filename).to_string_lossy().to_string();
       let dir_node = filesystem.search_dir_node_from_path_mut(&dirpath)?;
-      let basename = Path::new(&filename)
-        .file_name()
-        .unwrap_or(&OsStr::new(""))
-        .to_str()
-        .unwrap_or(&"");
-      let mut insert_file_node = FileNode::default();
-      if should_unpack || dir_node.unpacked.unwrap_or(false) {
-        insert_file_node.size = stat.len() as usize;
-        insert_file_node.unpacked = Some(true);
-        insert_file_node.integrity = Some(get_file_integrity(filename)?);
-        // filesystem.insert(&filename, Node::File(insert_file_node))?;
-        dir_node
-          .files
-          .insert(basename.to_owned(), Node::File(insert_file_node));
-        files.push(file_item);
-        continue;
-      }
-
-      let size: usize;
-      if let Some(transform) = &options.transform {
-        let maybe_transformer = transform(filename);
-        if let Some(mut transformer) = maybe_transformer {
-          let mut original_file = File::open(filename)?;
-          let mut tmpfile = tempfile::Builder::new().tempfile()?;
-          let mut buffer = vec![0u8; BUFFER_SIZE];
-          loop {
-            let mut read_size = original_file.read(&mut buffer)?;
-            if read_size == 0 {
-              transformer.flush()?;
-              read_size = transformer.read(&mut buffer)?;
-              if read_size > 0 {
-                tmpfile.write(&buffer[0..read_size])?;
-              }
-              tmpfile.flush()?;
-              break;
-            }
-            transformer.transform(&buffer[0..read_size])?;
-            read_size = transformer.read(&mut buffer)?;
-            if read_size > 0 {
-              tmpfile.write(&buffer[0..read_size])?;
-            }
-          }
-          size = tmpfile.as_file().metadata()?.len() as usize;
-          file_item.transformed_file = Some(tmpfile)
-        } else {
-          size = stat.len() as usize;
-        }
+      let relative_path = crate::filesystem::relative(&src, filename)?;
+      let unpacked = if should_unpack || dir_node.unpacked.unwrap_or(false) {
+        Some(writer.unpacked_path(&relative_path)?)
       } else {
-        size = stat.len() as usize;
-      }
+        None
+      };
+      files.push(PackItem {
+        filename: filename.clone(),
+        path: relative_path
+          .components()
+          .map(|component| component.as_os_str().to_string_lossy())
+          .collect::<Vec<_>>()
+          .join("/"),
+        stat: stat.clone(),
+        unpacked,
+        transformed: None,
+        offset: 0,
+        size: stat.len(),
+      });
+    } else if stat.is_symlink() {
+      filesystem.insert_link(filename)?;
+    }
+  }
 
-      if size > u32::MAX as usize {
-        return Err(Error::new(ErrorKind::FileTooLarge(filename.clone())));
+  // a transform decides the size of its file, and with it the offsets of
+  // every file after it, so all transforms run before anything is placed.
+  // Their outputs are kept by path, so that only the files being worked on
+  // are open at a time.
+  let staging = tempfile::Builder::new()
+    .prefix(".asar-transformed-")
+    .tempdir()?;
+  let indexed: Vec<(usize, &PackItem)> = files.iter().enumerate().collect();
+  let staged = crate::parallel::map(&indexed, options.threads, |(index, item)| {
+    transform_item(item, options, &staging.path().join(index.to_string()))
+  });
+  let mut kept: Vec<PackItem> = Vec::with_capacity(files.len());
+  for (mut item, staged) in files.into_iter().zip(staged) {
+    match staged? {
+      Staged::Original => {}
+      Staged::Transformed(transformed, size) => {
+        item.transformed = Some(transformed);
+        item.size = size;
+      }
+      Staged::Removed => continue,
+    }
+    if item.unpacked.is_none() {
+      if item.size > u32::MAX as u64 {
+        return Err(Error::new(ErrorKind::FileTooLarge(item.filename.clone())));
       }
+      item.offset = filesystem.offset;
+      filesystem.offset += item.size;
+    }
+    kept.push(item);
+  }
+  let files = kept;
 
-      insert_file_node.size = size;
-      insert_file_node.offset = Some(filesystem.offset.to_string());
-      insert_file_node.integrity = Some(get_file_integrity(filename)?);
+  // with every size known the header can be laid out, so the contents are
+  // written straight into the archive after it, and hashed on the way
+  for item in &files {
+    let placeholder = placeholder_integrity(item.size);
+    filesystem.insert(&item.filename, Node::File(file_node(item, placeholder)))?;
+  }
+  let data_offset = writer.reserve_header(&filesystem.header)?;
+  let data: &File = writer.data();
+  let copied = crate::parallel::map(&files, options.threads, |item| {
+    copy_item(item, data, data_offset)
+  });
+  for (item, copied) in files.iter().zip(copied) {
+    filesystem.insert(&item.filename, Node::File(file_node(item, copied?)))?;
+  }
 
-      #[cfg(not(target_os = "windows"))]
-      {
-        if stat.mode() & 0o100 != 0 {
-          insert_file_node.executable = Some(true);
-        }
+  writer.finish(&filesystem.header)
+}
+
+fn file_node(item: &PackItem, integrity: Integrity) -> FileNode {
+  let mut file_node = FileNode {
+    size: item.size as usize,
+    integrity: Some(integrity),
+    ..Default::default()
+  };
+  if item.unpacked.is_some() {
+    file_node.unpacked = Some(true);
+  } else {
+    file_node.offset = Some(item.offset.to_string());
+    #[cfg(not(target_os = "windows"))]
+    {
+      if item.stat.mode() & 0o100 != 0 {
+        file_node.executable = Some(true);
       }
+    }
+  }
+  file_node
+}
 
-      filesystem.offset += size as u64;
-      filesystem.insert(filename, Node::File(insert_file_node))?;
-      files.push(file_item);
-    } else if stat.is_symlink() {
-      filesystem.insert_link(&filename)?;
+/// A regular file on its way into an archive.
+struct PackItem {
+  filename: String,
+  /// Path inside the archive, with `/` separators.
+  path: String,
+  stat: Metadata,
+  /// Staging path in `.unpacked`, for files that are not packed.
+  unpacked: Option<PathBuf>,
+  /// Staging path of the transformed content, which is what gets packed and
+  /// hashed.
+  transformed: Option<PathBuf>,
+  offset: u64,
+  size: u64,
+}
+
+/// The content a file is packed with, once every transform ran.
+enum Staged {
+  Original,
+  Transformed(PathBuf, u64),
+  Removed,
+}
+
+/// Runs the transforms of `item`, staging their output at `staging` with an
+/// extension.
+fn transform_item(item: &PackItem, options: &CreateOptions, staging: &Path) -> Result<Staged> {
+  let transformer = match &options.transform {
+    Some(transform) if item.unpacked.is_none() => transform(&item.filename),
+    _ => None,
+  };
+  let staged = match transformer {
+    Some(transformer) => {
+      let target = staging.with_extension("legacy");
+      let mut staged = File::create(&target)?;
+      let size = transform_file(&mut File::open(&item.filename)?, transformer, &mut staged)?;
+      Staged::Transformed(target, size)
     }
+    None => Staged::Original,
+  };
+  if options.builtins.is_empty() && options.transforms.is_empty() {
+    return Ok(staged);
   }
 
-  std::fs::create_dir_all(get_dir(&dest))?;
-  crate::disk::write_filesystem(&dest, &filesystem, &mut files)?;
-  Ok(())
+  let context = TransformContext {
+    path: &item.path,
+    metadata: &item.stat,
+    unpacked: item.unpacked.is_some(),
+  };
+  let transform_error = |err| Error::new(ErrorKind::Transform(item.path.clone(), err));
+  let content: Box<dyn Read> = match &staged {
+    Staged::Transformed(staged, _) => Box::new(File::open(staged)?),
+    _ => Box::new(File::open(&item.filename)?),
+  };
+  let outputs = options
+    .builtins
+    .iter()
+    .map(|builtin| Ok(builtin.output(&context)))
+    .chain(options.transforms.iter().map(|hook| hook(&context)));
+  match crate::transform::apply(outputs, content).map_err(transform_error)? {
+    Applied::Content(mut content) => {
+      let target = staging.with_extension("transformed");
+      let size =
+        std::io::copy(&mut content, &mut File::create(&target)?).map_err(transform_error)?;
+      Ok(Staged::Transformed(target, size))
+    }
+    Applied::Unchanged => Ok(staged),
+    Applied::Removed => Ok(Staged::Removed),
+  }
+}
+
+/// Copies the content of `item` to its place in `data`, whose packed
+/// contents start at `data_offset`, or in `.unpacked`, and returns its
+/// integrity.
+fn copy_item(item: &PackItem, data: &File, data_offset: u64) -> Result<Integrity> {
+  let content: Box<dyn Read> = match &item.transformed {
+    Some(staged) => Box::new(File::open(staged)?),
+    None => Box::new(File::open(&item.filename)?),
+  };
+
+  let (size, integrity) = match &item.unpacked {
+    Some(target) => {
+      let copied = copy_with_integrity(content.take(item.size), &mut File::create(target)?)?;
+      std::fs::set_permissions(target, item.stat.permissions())?;
+      copied
+    }
+    None => {
+      let mut dest = OffsetWriter::new(data, data_offset + item.offset);
+      copy_with_integrity(content.take(item.size), &mut dest)?
+    }
+  };
+  if size != item.size {
+    // the header and the offsets of later files were already laid out
+    return Err(
+      std::io::Error::new(
+        std::io::ErrorKind::UnexpectedEof,
+        format!("{} changed while packing", item.filename),
+      )
+      .into(),
+    );
+  }
+  Ok(integrity)
+}
+
+/// Streams `original` through `transformer` into `dest` and returns the
+/// number of bytes written.
+fn transform_file<R: Read, W: Write>(
+  original: &mut R,
+  mut transformer: Box<dyn Transform>,
+  dest: &mut W,
+) -> Result<u64> {
+  let mut size: u64 = 0;
+  let mut buffer = vec![0u8; BUFFER_SIZE];
+  loop {
+    let mut read_size = original.read(&mut buffer)?;
+    if read_size == 0 {
+      transformer.flush()?;
+      read_size = transformer.read(&mut buffer)?;
+      dest.write_all(&buffer[0..read_size])?;
+      size += read_size as u64;
+      break;
+    }
+    transformer.transform(&buffer[0..read_size])?;
+    read_size = transformer.read(&mut buffer)?;
+    dest.write_all(&buffer[0..read_size])?;
+    size += read_size as u64;
+  }
+  Ok(size)
 }
 
 fn multiple_pattern(pattern: &str) -> Option<(usize, usize, Vec<&str>)> {
@@ -510,13 +994,13 @@ fn multiple_pattern(pattern: &str) -> Option<(usize, usize, Vec<&str>)> {
   }
 }
 
-fn minimatch(path: &str, pattern: &str, match_base: bool) -> Result<bool> {
+pub(crate) fn minimatch(path: &str, pattern: &str, match_base: bool) -> Result<bool> {
   let value = if match_base {
     Path::new(path)
       .file_name()
-      .unwrap_or(&OsStr::new(""))
+      .unwrap_or(OsStr::new(""))
       .to_str()
-      .unwrap_or(&"")
+      .unwrap_or("")
   } else {
     path
   };
@@ -528,7 +1012,7 @@ fn minimatch(path: &str, pattern: &str, match_base: bool) -> Result<bool> {
     let mut should_continue = false;
     patterns = patterns
       .iter()
-      .map(|pattern| {
+      .flat_map(|pattern| {
         if let Some((begin, end, items)) = multiple_pattern(pattern) {
           should_continue = true;
           let sub: Vec<String> = items
@@ -540,7 +1024,6 @@ fn minimatch(path: &str, pattern: &str, match_base: bool) -> Result<bool> {
           vec![pattern.clone()]
         }
       })
-      .flatten()
       .collect();
     if !should_continue {
       break;
@@ -564,7 +1047,7 @@ fn is_unpacked_dir(dir_path: &str, pattern: &str, unpack_dirs: &mut Vec<String>)
     if !unpack_dirs.contains(&dir_path_string) {
       unpack_dirs.push(dir_path_string);
     }
-    return Ok(true);
+    Ok(true)
   } else {
     match unpack_dirs
       .iter()
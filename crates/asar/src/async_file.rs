@@ -0,0 +1,205 @@
+use std::{
+  future::Future,
+  io::{Read, Seek, SeekFrom},
+  path::Path,
+  pin::Pin,
+  sync::Arc,
+  task::{ready, Context, Poll},
+};
+
+use tokio::{
+  io::{AsyncRead, AsyncSeek, ReadBuf},
+  task::JoinHandle,
+};
+
+use crate::{
+  asar::AsarFile,
+  error::Result,
+  integrity::BUFFER_SIZE,
+  node::{FileNode, Node},
+  ExtractOptions, ListOptions,
+};
+
+async fn blocking<F, T>(f: F) -> Result<T>
+where
+  F: FnOnce() -> Result<T> + Send + 'static,
+  T: Send + 'static,
+{
+  tokio::task::spawn_blocking(f)
+    .await
+    .map_err(std::io::Error::other)?
+}
+
+/// The tokio counterpart of `AsarFile`.
+///
+/// The header is parsed and looked up exactly like `AsarFile` does. Reads
+/// and extraction run on the blocking thread pool, and so do the reads of
+/// `open_entry`, through the source and unpacked provider of the archive.
+/// None of them block the runtime.
+#[derive(Clone)]
+pub struct AsyncAsarFile {
+  inner: Arc<AsarFile>,
+}
+
+impl AsyncAsarFile {
+  pub async fn open<T: AsRef<Path>>(archive: T) -> Result<Self> {
+    let archive = archive.as_ref().to_path_buf();
+    let inner = blocking(move || AsarFile::open(archive)).await?;
+    Ok(AsyncAsarFile {
+      inner: Arc::new(inner),
+    })
+  }
+
+  /// Wraps an archive that is already open.
+  pub fn from_asar(asar: AsarFile) -> Self {
+    AsyncAsarFile {
+      inner: Arc::new(asar),
+    }
+  }
+
+  pub fn stat_file(&self, p: &str, follow_links: Option<bool>) -> Result<&Node> {
+    self.inner.stat_file(p, follow_links)
+  }
+
+  pub fn list(&self, options: &ListOptions) -> Result<Vec<String>> {
+    self.inner.list(options)
+  }
+
+  pub async fn read_file(&self, filename: &str) -> Result<Vec<u8>> {
+    let inner = self.inner.clone();
+    let filename = filename.to_owned();
+    blocking(move || inner.read_file(&filename)).await
+  }
+
+  /// Opens a streaming reader over the content of `filename`, following links.
+  pub async fn open_entry(&self, filename: &str) -> Result<AsyncEntryReader> {
+    let (path, node) = self.inner.get_file_node(filename)?;
+    // unpacked files are opened once up front, for their size and so that a
+    // missing one fails here instead of on the first read
+    let inner = self.inner.clone();
+    let (entry_path, entry_node) = (path.clone(), node.clone());
+    let size = blocking(move || Ok(inner.open_file_node(&entry_path, &entry_node)?.size())).await?;
+    Ok(AsyncEntryReader {
+      inner: self.inner.clone(),
+      path,
+      node,
+      size,
+      pos: 0,
+      buffer: vec![],
+      reading: None,
+    })
+  }
+
+  pub async fn extract_file<T: AsRef<Path>>(&self, filename: &str, dest: T) -> Result<()> {
+    let inner = self.inner.clone();
+    let filename = filename.to_owned();
+    let dest = dest.as_ref().to_path_buf();
+    blocking(move || inner.extract_file(&filename, dest)).await
+  }
+
+  pub async fn extract_all<T: AsRef<Path>>(&self, dest: T) -> Result<()> {
+    self
+      .extract_all_with_options(dest, &ExtractOptions::new())
+      .await
+  }
+
+  pub async fn extract_all_with_options<T: AsRef<Path>>(
+    &self,
+    dest: T,
+    options: &ExtractOptions,
+  ) -> Result<()> {
+    let inner = self.inner.clone();
+    let dest = dest.as_ref().to_path_buf();
+    let options = options.clone();
+    blocking(move || inner.extract_all_with_options(dest, &options)).await
+  }
+}
+
+/// An `AsyncRead + AsyncSeek` handle over the bytes of a single archive
+/// entry, with the same bounds as `EntryReader`.
+pub struct AsyncEntryReader {
+  inner: Arc<AsarFile>,
+  path: String,
+  node: FileNode,
+  size: u64,
+  pos: u64,
+  // bytes read ahead of `pos`, handed out before anything is read again
+  buffer: Vec<u8>,
+  reading: Option<JoinHandle<std::io::Result<Vec<u8>>>>,
+}
+
+impl AsyncEntryReader {
+  /// Size of the entry in bytes.
+  pub fn size(&self) -> u64 {
+    self.size
+  }
+
+  fn start_read(&mut self, len: usize) {
+    let inner = self.inner.clone();
+    let path = self.path.clone();
+    let node = self.node.clone();
+    let pos = self.pos;
+    self.reading = Some(tokio::task::spawn_blocking(move || {
+      let mut entry = inner
+        .open_file_node(&path, &node)
+        .map_err(std::io::Error::other)?;
+      entry.seek(SeekFrom::Start(pos))?;
+      let mut chunk = vec![0u8; len];
+      let read_size = entry.read(&mut chunk)?;
+      chunk.truncate(read_size);
+      Ok(chunk)
+    }));
+  }
+}
+
+impl AsyncRead for AsyncEntryReader {
+  fn poll_read(
+    mut self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    buf: &mut ReadBuf<'_>,
+  ) -> Poll<std::io::Result<()>> {
+    if self.pos >= self.size || buf.remaining() == 0 {
+      return Poll::Ready(Ok(()));
+    }
+    if self.buffer.is_empty() {
+      if self.reading.is_none() {
+        let len = std::cmp::min(self.size - self.pos, BUFFER_SIZE as u64) as usize;
+        self.start_read(len);
+      }
+      let reading = self.reading.as_mut().expect("a read was started");
+      let chunk = ready!(Pin::new(reading).poll(cx));
+      self.reading = None;
+      self.buffer = chunk.map_err(std::io::Error::other)??;
+    }
+    let read_size = std::cmp::min(self.buffer.len(), buf.remaining());
+    buf.put_slice(&self.buffer[..read_size]);
+    self.buffer.drain(..read_size);
+    self.pos += read_size as u64;
+    Poll::Ready(Ok(()))
+  }
+}
+
+impl AsyncSeek for AsyncEntryReader {
+  fn start_seek(mut self: Pin<&mut Self>, position: SeekFrom) -> std::io::Result<()> {
+    let pos = match position {
+      SeekFrom::Start(n) => Some(n),
+      SeekFrom::End(n) => self.size.checked_add_signed(n),
+      SeekFrom::Current(n) => self.pos.checked_add_signed(n),
+    }
+    .ok_or_else(|| {
+      std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        "invalid seek to a negative or overflowing position",
+      )
+    })?;
+    // reads are positional, so one still in flight is simply forgotten
+    self.reading = None;
+    self.buffer.clear();
+    self.pos = pos;
+    Ok(())
+  }
+
+  fn poll_complete(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<u64>> {
+    Poll::Ready(Ok(self.pos))
+  }
+}
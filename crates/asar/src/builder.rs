@@ -0,0 +1,272 @@
+use std::{
+  fs::File,
+  io::{Cursor, Read},
+  path::{Path, PathBuf},
+};
+
+use crate::{
+  disk::{ArchiveWriter, OffsetWriter},
+  error::{Error, ErrorKind, Result},
+  filesystem::{get_dir, is_valid_entry_name, is_valid_link_target, Filesystem},
+  integrity::{copy_with_integrity, placeholder_integrity},
+  node::{DirectoryNode, FileNode, HeaderIntegrity, LinkNode, Node},
+};
+
+/// Content of a file added to an [`AsarBuilder`].
+pub enum FileSource {
+  Bytes(Vec<u8>),
+  Reader(Box<dyn Read>),
+}
+
+impl FileSource {
+  pub fn reader<R: Read + 'static>(reader: R) -> Self {
+    FileSource::Reader(Box::new(reader))
+  }
+
+  fn into_reader(self) -> Box<dyn Read> {
+    match self {
+      FileSource::Bytes(bytes) => Box::new(Cursor::new(bytes)),
+      FileSource::Reader(reader) => reader,
+    }
+  }
+}
+
+/// Content of a packed file, once its size is known.
+enum PackedSource {
+  Bytes(Vec<u8>),
+  Spooled(PathBuf),
+}
+
+impl From<Vec<u8>> for FileSource {
+  fn from(value: Vec<u8>) -> Self {
+    FileSource::Bytes(value)
+  }
+}
+
+impl From<&[u8]> for FileSource {
+  fn from(value: &[u8]) -> Self {
+    FileSource::Bytes(value.to_vec())
+  }
+}
+
+impl From<String> for FileSource {
+  fn from(value: String) -> Self {
+    FileSource::Bytes(value.into_bytes())
+  }
+}
+
+impl From<&str> for FileSource {
+  fn from(value: &str) -> Self {
+    FileSource::Bytes(value.as_bytes().to_vec())
+  }
+}
+
+/// Builds an archive from entries supplied by code instead of a directory on
+/// disk.
+///
+/// Paths are relative to the archive root and use `/` or `\` as separators.
+/// Packed files are laid out in the order they were added.
+pub struct AsarBuilder {
+  filesystem: Filesystem,
+  sources: Vec<(String, FileSource)>,
+}
+
+impl Default for AsarBuilder {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl AsarBuilder {
+  pub fn new() -> Self {
+    AsarBuilder {
+      filesystem: Filesystem::new(PathBuf::new()),
+      sources: vec![],
+    }
+  }
+
+  /// Adds a file, replacing any entry already at `path`. Missing parent
+  /// directories are created.
+  pub fn add_file<S: Into<FileSource>>(
+    &mut self,
+    path: &str,
+    source: S,
+    executable: bool,
+  ) -> Result<()> {
+    let path = normalize(path)?;
+    let mut node = FileNode::default();
+    if executable {
+      node.executable = Some(true);
+    }
+    self.insert(&path, Node::File(node))?;
+    self.sources.push((path, source.into()));
+    Ok(())
+  }
+
+  /// Adds an empty directory. Adding an existing directory keeps its content.
+  pub fn add_dir(&mut self, path: &str) -> Result<()> {
+    let path = normalize(path)?;
+    self.insert(&path, Node::Directory(DirectoryNode::default()))
+  }
+
+  /// Adds a link to `target`, which is relative to the archive root.
+  pub fn add_symlink(&mut self, path: &str, target: &str) -> Result<()> {
+    let path = normalize(path)?;
+    if !is_valid_link_target(target) {
+      return Err(Error::new(ErrorKind::BadLink(path.into(), target.into())));
+    }
+    self.insert(
+      &path,
+      Node::Link(LinkNode {
+        link: target.to_owned(),
+      }),
+    )
+  }
+
+  /// Stores a file, or every file below a directory, in `.unpacked` instead
+  /// of the archive.
+  pub fn mark_unpacked(&mut self, path: &str) -> Result<()> {
+    let path = normalize(path)?;
+    let node = self.node_mut(&path)?;
+    if node.is_link() {
+      return Err(Error::new(ErrorKind::ExpectFileNode(path)));
+    }
+    node.set_unpacked(true);
+    Ok(())
+  }
+
+  /// Writes the archive to `dest` and the unpacked files to `dest.unpacked`.
+  pub fn write<T: AsRef<Path>>(mut self, dest: T) -> Result<HeaderIntegrity> {
+    std::fs::create_dir_all(get_dir(&dest))?;
+    let mut writer = ArchiveWriter::new(&dest)?;
+    let sources = std::mem::take(&mut self.sources);
+    // the header goes in front of the packed contents and holds their sizes,
+    // so readers are spooled to disk before anything is packed
+    let spool = tempfile::Builder::new().prefix(".asar-spool-").tempdir()?;
+    let mut packed: Vec<(String, PackedSource, u64)> = vec![];
+
+    for (index, (path, source)) in sources.into_iter().enumerate() {
+      if self.is_unpacked(&path) {
+        let mut options = std::fs::OpenOptions::new();
+        options.create(true).write(true).truncate(true);
+        // like any new executable, once the umask is applied
+        #[cfg(not(target_os = "windows"))]
+        if let Node::File(FileNode {
+          executable: Some(true),
+          ..
+        }) = self.node_mut(&path)?
+        {
+          use std::os::unix::fs::OpenOptionsExt;
+          options.mode(0o777);
+        }
+        let mut fd = options.open(writer.unpacked_path(&path)?)?;
+        let (size, integrity) = copy_with_integrity(source.into_reader(), &mut fd)?;
+        if let Node::File(node) = self.node_mut(&path)? {
+          node.size = size as usize;
+          node.integrity = Some(integrity);
+          node.unpacked = Some(true);
+          // the mode of the unpacked file tells, as it does when packing a
+          // directory
+          node.executable = None;
+        }
+        continue;
+      }
+
+      let (source, size) = match source {
+        FileSource::Bytes(bytes) => {
+          let size = bytes.len() as u64;
+          (PackedSource::Bytes(bytes), size)
+        }
+        FileSource::Reader(mut reader) => {
+          let target = spool.path().join(index.to_string());
+          let size = std::io::copy(&mut reader, &mut File::create(&target)?)?;
+          (PackedSource::Spooled(target), size)
+        }
+      };
+      if size > u32::MAX as u64 {
+        return Err(Error::new(ErrorKind::FileTooLarge(path)));
+      }
+
+      let offset = self.filesystem.offset;
+      if let Node::File(node) = self.node_mut(&path)? {
+        node.size = size as usize;
+        node.integrity = Some(placeholder_integrity(size));
+        node.offset = Some(offset.to_string());
+      }
+      self.filesystem.offset += size;
+      packed.push((path, source, offset));
+    }
+
+    let data_offset = writer.reserve_header(&self.filesystem.header)?;
+    for (path, source, offset) in packed {
+      let reader: Box<dyn Read> = match source {
+        PackedSource::Bytes(bytes) => Box::new(Cursor::new(bytes)),
+        PackedSource::Spooled(spooled) => Box::new(File::open(spooled)?),
+      };
+      let mut dest = OffsetWriter::new(writer.data(), data_offset + offset);
+      let (_, integrity) = copy_with_integrity(reader, &mut dest)?;
+      if let Node::File(node) = self.node_mut(&path)? {
+        node.integrity = Some(integrity);
+      }
+    }
+
+    writer.finish(&self.filesystem.header)
+  }
+
+  fn insert(&mut self, path: &str, node: Node) -> Result<()> {
+    let (dir, name) = path.rsplit_once('/').unwrap_or((".", path));
+    match self.filesystem.search_node_from_directory_mut(dir)? {
+      Node::Directory(DirectoryNode { files, .. }) => {
+        if node.is_dir() && matches!(files.get(name), Some(Node::Directory(..))) {
+          return Ok(());
+        }
+        files.insert(name.to_owned(), node);
+      }
+      _ => return Err(Error::new(ErrorKind::ExpectDirNode(dir.to_owned()))),
+    }
+    // whatever was at `path` before is gone, and so is its content
+    let prefix = path.to_owned() + "/";
+    self
+      .sources
+      .retain(|(p, _)| p != path && !p.starts_with(&prefix));
+    Ok(())
+  }
+
+  fn node_mut(&mut self, path: &str) -> Result<&mut Node> {
+    let mut node = &mut self.filesystem.header;
+    for name in path.split('/') {
+      node = match node {
+        Node::Directory(DirectoryNode { files, .. }) => files
+          .get_mut(name)
+          .ok_or_else(|| Error::new(ErrorKind::NoSuchEntry(path.to_owned())))?,
+        _ => return Err(Error::new(ErrorKind::ExpectDirNode(path.to_owned()))),
+      };
+    }
+    Ok(node)
+  }
+
+  fn is_unpacked(&self, path: &str) -> bool {
+    let mut node = &self.filesystem.header;
+    for name in path.split('/') {
+      if node.unpacked() {
+        return true;
+      }
+      node = match node.as_dir_node().and_then(|dir| dir.files.get(name)) {
+        Some(child) => child,
+        None => return false,
+      };
+    }
+    node.unpacked()
+  }
+}
+
+fn normalize(path: &str) -> Result<String> {
+  let names: Vec<&str> = path
+    .split(['/', '\\'])
+    .filter(|name| !name.is_empty() && *name != ".")
+    .collect();
+  if names.is_empty() || !names.iter().all(|name| is_valid_entry_name(name)) {
+    return Err(Error::new(ErrorKind::PathTraversal(path.to_owned())));
+  }
+  Ok(names.join("/"))
+}
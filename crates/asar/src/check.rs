@@ -0,0 +1,211 @@
+use std::fmt::Display;
+
+use serde::Serialize;
+
+use crate::{
+  filesystem::{is_valid_entry_name, is_valid_link_target, Filesystem},
+  node::{DirectoryNode, FileNode, LinkNode, Node},
+  source::UnpackedProvider,
+};
+
+/// A structural problem found by `AsarFile::check`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum Finding {
+  /// The byte range of a packed file ends past the archive data.
+  OutOfBounds {
+    path: String,
+    offset: u64,
+    size: u64,
+  },
+  /// The byte range of a packed file overlaps the range of `other`.
+  Overlap {
+    path: String,
+    other: String,
+    offset: u64,
+    size: u64,
+  },
+  /// Bytes of the archive data that no file refers to.
+  Gap { offset: u64, size: u64 },
+  /// A link whose target does not exist in the archive.
+  MissingLinkTarget { path: String, link: String },
+  /// A link whose target points outside of the archive.
+  IllegalLink { path: String, link: String },
+  /// An unpacked file that is missing from the `.unpacked` directory.
+  MissingUnpacked { path: String },
+  /// A packed file whose `offset` is missing or is not a number.
+  InvalidOffset {
+    path: String,
+    offset: Option<String>,
+  },
+  /// A file without `integrity`.
+  MissingIntegrity { path: String },
+  /// An entry name that can not be used as a path component.
+  IllegalName { path: String },
+}
+
+impl Display for Finding {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::OutOfBounds { path, offset, size } => write!(
+        f,
+        "{}: {} bytes at offset {} are out of the archive data",
+        path, size, offset
+      ),
+      Self::Overlap {
+        path,
+        other,
+        offset,
+        size,
+      } => write!(
+        f,
+        "{}: {} bytes at offset {} overlap \"{}\"",
+        path, size, offset, other
+      ),
+      Self::Gap { offset, size } => {
+        write!(f, "{} unreferenced bytes at offset {}", size, offset)
+      }
+      Self::MissingLinkTarget { path, link } => {
+        write!(f, "{}: link target \"{}\" does not exist", path, link)
+      }
+      Self::IllegalLink { path, link } => {
+        write!(
+          f,
+          "{}: link target \"{}\" is outside of the archive",
+          path, link
+        )
+      }
+      Self::MissingUnpacked { path } => write!(f, "{}: unpacked file is missing", path),
+      Self::InvalidOffset { path, offset } => match offset {
+        Some(offset) => write!(f, "{}: offset \"{}\" is not a number", path, offset),
+        None => write!(f, "{}: packed file has no offset", path),
+      },
+      Self::MissingIntegrity { path } => write!(f, "{}: missing integrity", path),
+      Self::IllegalName { path } => write!(f, "{}: illegal entry name", path),
+    }
+  }
+}
+
+pub fn check_filesystem(
+  filesystem: &Filesystem,
+  data_size: u64,
+  provider: Option<&dyn UnpackedProvider>,
+) -> Vec<Finding> {
+  let mut findings: Vec<Finding> = vec![];
+  let mut ranges: Vec<(u64, u64, String)> = vec![];
+
+  fn walk(
+    filesystem: &Filesystem,
+    provider: Option<&dyn UnpackedProvider>,
+    base_path: &str,
+    node: &Node,
+    findings: &mut Vec<Finding>,
+    ranges: &mut Vec<(u64, u64, String)>,
+  ) {
+    let path = base_path.to_owned();
+    match node {
+      Node::Directory(DirectoryNode { files, .. }) => {
+        for (name, child) in files {
+          let full_path = if base_path.is_empty() {
+            name.clone()
+          } else {
+            base_path.to_owned() + "/" + name
+          };
+          if !is_valid_entry_name(name) {
+            findings.push(Finding::IllegalName {
+              path: full_path.clone(),
+            });
+          }
+          walk(filesystem, provider, &full_path, child, findings, ranges);
+        }
+      }
+      Node::Link(LinkNode { link }) => {
+        if !is_valid_link_target(link) {
+          findings.push(Finding::IllegalLink {
+            path,
+            link: link.clone(),
+          });
+        } else if filesystem.resolve(&path, true).is_err() {
+          findings.push(Finding::MissingLinkTarget {
+            path,
+            link: link.clone(),
+          });
+        }
+      }
+      Node::File(FileNode {
+        size,
+        offset,
+        unpacked,
+        integrity,
+        ..
+      }) => {
+        if integrity.is_none() {
+          findings.push(Finding::MissingIntegrity { path: path.clone() });
+        }
+        if unpacked.unwrap_or(false) {
+          if !matches!(provider, Some(provider) if provider.exists(&path)) {
+            findings.push(Finding::MissingUnpacked { path });
+          }
+          return;
+        }
+        match offset.as_ref().map(|offset| offset.parse::<u64>()) {
+          Some(Ok(offset)) => ranges.push((offset, *size as u64, path)),
+          _ => findings.push(Finding::InvalidOffset {
+            path,
+            offset: offset.clone(),
+          }),
+        }
+      }
+    }
+  }
+
+  walk(
+    filesystem,
+    provider,
+    "",
+    &filesystem.header,
+    &mut findings,
+    &mut ranges,
+  );
+
+  ranges.sort();
+  let mut cursor: u64 = 0;
+  let mut cursor_path = String::new();
+  for (offset, size, path) in ranges {
+    let end = match offset.checked_add(size) {
+      Some(end) if end <= data_size => end,
+      _ => {
+        findings.push(Finding::OutOfBounds { path, offset, size });
+        continue;
+      }
+    };
+    if size == 0 {
+      continue;
+    }
+    if offset > cursor {
+      findings.push(Finding::Gap {
+        offset: cursor,
+        size: offset - cursor,
+      });
+    } else if offset < cursor {
+      findings.push(Finding::Overlap {
+        path: path.clone(),
+        other: cursor_path.clone(),
+        offset,
+        size,
+      });
+    }
+    if end > cursor {
+      cursor = end;
+      cursor_path = path;
+    }
+  }
+  if cursor < data_size {
+    findings.push(Finding::Gap {
+      offset: cursor,
+      size: data_size - cursor,
+    });
+  }
+
+  findings
+}
@@ -0,0 +1,119 @@
+use std::{
+  fs::File,
+  io::{Read, Seek, SeekFrom},
+  os::unix::io::AsRawFd,
+};
+
+// a single call copies at most this much, like the kernel itself does
+const MAX_CHUNK: u64 = 1 << 30;
+
+/// Copies `size` bytes starting at `start` of `src` to the current position
+/// of `dest` with `copy_file_range`, so the data never passes through user
+/// space. The offset is passed explicitly, so `src` can be shared between
+/// threads.
+///
+/// Returns how many bytes were copied, which is less than `size` when the
+/// kernel or the file systems can not do it. The caller copies the rest.
+pub fn copy_file_range(src: &File, start: u64, size: u64, dest: &File) -> std::io::Result<u64> {
+  copy_chunks(size, |copied, len| {
+    let mut off_in = (start + copied) as libc::loff_t;
+    // Safety: both descriptors are open for the duration of the call and
+    // `off_in` is a valid pointer, while a null `off_out` makes the kernel use
+    // and advance the position of `dest`.
+    let ret = unsafe {
+      libc::copy_file_range(
+        src.as_raw_fd(),
+        &mut off_in,
+        dest.as_raw_fd(),
+        std::ptr::null_mut(),
+        len,
+        0,
+      )
+    };
+    if ret < 0 {
+      return Err(std::io::Error::last_os_error());
+    }
+    Ok(ret as u64)
+  })
+}
+
+/// Calls `copy_chunk` with the number of bytes copied so far and the length
+/// of the next chunk, until `size` bytes are copied or the kernel gives up.
+fn copy_chunks<F>(size: u64, mut copy_chunk: F) -> std::io::Result<u64>
+where
+  F: FnMut(u64, usize) -> std::io::Result<u64>,
+{
+  let mut copied: u64 = 0;
+  while copied < size {
+    let len = std::cmp::min(size - copied, MAX_CHUNK) as usize;
+    match copy_chunk(copied, len) {
+      // the source ended early, let the caller report it
+      Ok(0) => break,
+      Ok(chunk) => copied += chunk,
+      Err(err) => match err.raw_os_error() {
+        Some(libc::EINTR) => continue,
+        Some(libc::ENOSYS | libc::EXDEV | libc::EOPNOTSUPP | libc::EINVAL | libc::EPERM) => break,
+        _ => return Err(err),
+      },
+    }
+  }
+  Ok(copied)
+}
+
+/// Copies all of `entry` to `dest`, first with `kernel_copy` and then
+/// through `entry` from wherever the kernel stopped.
+pub fn copy_resuming<R, F>(entry: &mut R, dest: &mut File, kernel_copy: F) -> std::io::Result<u64>
+where
+  R: Read + Seek,
+  F: FnOnce(&File) -> std::io::Result<u64>,
+{
+  let copied = kernel_copy(dest)?;
+  entry.seek(SeekFrom::Start(copied))?;
+  Ok(copied + std::io::copy(entry, dest)?)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::io::{Cursor, Write};
+
+  fn os_error(code: i32) -> std::io::Error {
+    std::io::Error::from_raw_os_error(code)
+  }
+
+  #[test]
+  fn stops_where_the_kernel_gives_up() {
+    let mut calls = vec![
+      Ok(3),
+      Err(os_error(libc::EINTR)),
+      Ok(2),
+      Err(os_error(libc::EXDEV)),
+    ];
+    calls.reverse();
+    let copied = copy_chunks(10, |_, _| calls.pop().unwrap()).unwrap();
+    assert_eq!(copied, 5);
+
+    assert_eq!(copy_chunks(10, |_, _| Ok(0)).unwrap(), 0);
+    let err = copy_chunks(10, |_, _| Err(os_error(libc::EIO))).unwrap_err();
+    assert_eq!(err.raw_os_error(), Some(libc::EIO));
+  }
+
+  #[test]
+  fn resumes_a_partial_copy() -> std::result::Result<(), Box<dyn std::error::Error>> {
+    let content: Vec<u8> = (0..100u8).collect();
+    for kernel_copied in [0, 37, 100] {
+      let mut entry = Cursor::new(content.clone());
+      let mut dest = tempfile::tempfile()?;
+      let copied = copy_resuming(&mut entry, &mut dest, |mut dest| {
+        dest.write_all(&content[..kernel_copied])?;
+        Ok(kernel_copied as u64)
+      })?;
+      assert_eq!(copied, 100);
+      dest.seek(SeekFrom::Start(0))?;
+      let mut written = vec![];
+      dest.read_to_end(&mut written)?;
+      assert_eq!(written, content);
+    }
+    Ok(())
+  }
+}
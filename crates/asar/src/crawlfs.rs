@@ -18,7 +18,7 @@ pub fn crawl_filesystem<T: AsRef<Path>>(
     .map(|filename| -> Result<(String, Metadata)> {
       let str = filename?;
       let stat = determine_file_type(&str)?;
-      return Ok((str.to_string_lossy().as_ref().to_owned(), stat));
+      Ok((str.to_string_lossy().as_ref().to_owned(), stat))
     })
     .collect();
   let results = results?;
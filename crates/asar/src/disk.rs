@@ -4,29 +4,60 @@ use std::io::{Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
 
 use chromium_pickle::Pickle;
-use tempfile::NamedTempFile;
+use tempfile::{NamedTempFile, TempDir};
 
 use crate::filesystem::get_dir;
+use crate::integrity::get_header_integrity;
 use crate::{
   error::{Error, ErrorKind, Result},
-  node::Node,
+  node::{HeaderIntegrity, Node},
 };
 
 pub fn read_archive_header<T: AsRef<Path>>(archive: T) -> Result<(String, Node, usize, File)> {
   let mut fd = File::open(archive)?;
+  let (header, json_value, size, _) = read_header(&mut fd, 0, None)?;
+  Ok((header, json_value, size, fd))
+}
+
+/// Parses the header of an archive that starts `base_offset` bytes into
+/// `reader` and spans `len` bytes, or up to the end of `reader` if `len` is
+/// not given. Returns the header string, its parsed form, the header size
+/// and the length of the archive.
+pub fn read_header<R: Read + Seek + ?Sized>(
+  reader: &mut R,
+  base_offset: u64,
+  len: Option<u64>,
+) -> Result<(String, Node, usize, u64)> {
+  let available = reader.seek(SeekFrom::End(0))?.saturating_sub(base_offset);
+  let len = len.map_or(available, |len| std::cmp::min(len, available));
+  reader.seek(SeekFrom::Start(base_offset))?;
   let mut size_buf = [0u8; 8];
-  fd.read(&mut size_buf)
+  reader
+    .read_exact(&mut size_buf)
     .map_err(|_| Error::new(ErrorKind::InvalidHeaderSize))?;
   let size_pickle = Pickle::from_slice(&size_buf);
-  let size = size_pickle.create_iterator().read_uint32() as usize;
+  let size = size_pickle
+    .create_iterator()
+    .read_uint32()
+    .ok_or_else(|| Error::new(ErrorKind::InvalidHeaderSize))? as usize;
+  if size as u64 > len.saturating_sub(8) {
+    return Err(Error::new(ErrorKind::InvalidHeaderSize));
+  }
   let mut header_buf = vec![0u8; size];
-  fd.read(&mut header_buf)
+  reader
+    .read_exact(&mut header_buf)
     .map_err(|_| Error::new(ErrorKind::InvalidHeader))?;
 
   let header_pickle = Pickle::from_vec(header_buf);
-  let header = header_pickle.create_iterator().read_string();
-  let json_value = serde_json::from_str(&header)?;
-  Ok((header, json_value, size, fd))
+  let header = header_pickle
+    .create_iterator()
+    .read_string()
+    .ok_or_else(|| Error::new(ErrorKind::InvalidHeader))?;
+  let json_value: Node = serde_json::from_str(&header)?;
+  if !json_value.is_dir() {
+    return Err(Error::new(ErrorKind::InvalidHeader));
+  }
+  Ok((header, json_value, size, len))
 }
 
 // pub fn read_filesystem<T: AsRef<Path>>(archive: T) -> Result<Filesystem> {
@@ -38,51 +69,199 @@ pub fn read_archive_header<T: AsRef<Path>>(archive: T) -> Result<(String, Node,
 //   Ok(filesystem)
 // }
 
-pub struct FileItem {
-  pub filename: String,
-  pub unpack: bool,
-  pub transformed_file: Option<NamedTempFile>,
+/// Stages a new archive and its `.unpacked` directory next to `dest`, then
+/// moves both into place, so an interrupted pack never leaves a half-written
+/// archive behind.
+pub struct ArchiveWriter {
+  dest: PathBuf,
+  dest_dir: PathBuf,
+  archive: NamedTempFile,
+  // size of the header that `reserve_header` made room for
+  header_size: Option<usize>,
+  unpacked: Option<TempDir>,
 }
 
-pub fn write_filesystem<T: AsRef<Path>>(
-  dest: T,
-  filesystem: &crate::filesystem::Filesystem,
-  files: &mut Vec<FileItem>,
-) -> Result<()> {
-  let mut header_pickle = Pickle::new();
-  header_pickle.write_string(&serde_json::to_string(&filesystem.header)?);
-  let header_buf = header_pickle.to_vec();
+impl ArchiveWriter {
+  pub fn new<T: AsRef<Path>>(dest: T) -> Result<Self> {
+    let dest = dest.as_ref().to_path_buf();
+    let dest_dir = get_dir(&dest);
+    let mut builder = tempfile::Builder::new();
+    // like any new file, once the umask is applied
+    #[cfg(not(target_os = "windows"))]
+    {
+      use std::os::unix::fs::PermissionsExt;
+      builder.permissions(std::fs::Permissions::from_mode(0o666));
+    }
+    let archive = builder.prefix(".asar-").tempfile_in(&dest_dir)?;
+    Ok(ArchiveWriter {
+      dest,
+      dest_dir,
+      archive,
+      header_size: None,
+      unpacked: None,
+    })
+  }
+
+  /// Makes room for a header laid out like `header`, and returns the offset
+  /// that packed file contents start at in `data`.
+  ///
+  /// The header given to `finish` may only differ in fixed-width values,
+  /// such as the hashes of the files.
+  pub fn reserve_header(&mut self, header: &Node) -> Result<u64> {
+    let (_, header_buf) = pickle_header(header)?;
+    self.header_size = Some(header_buf.len());
+    Ok(8 + header_buf.len() as u64)
+  }
 
-  let mut size_pickle = Pickle::new();
-  size_pickle.write_uint32(header_buf.len() as u32);
-  let size_buf = size_pickle.to_vec();
-
-  let mut options = std::fs::OpenOptions::new();
-  options.create(true).write(true);
-  let mut asar = options.open(&dest)?;
-
-  asar.write(&size_buf)?;
-  asar.write(&header_buf)?;
-
-  for f in files.iter_mut() {
-    if f.unpack {
-      let filename = crate::filesystem::relative(&filesystem.src, &f.filename)?;
-      let target =
-        PathBuf::from(dest.as_ref().to_string_lossy().to_string() + &".unpacked").join(filename);
-      std::fs::create_dir_all(get_dir(&target))?;
-      std::fs::copy(&f.filename, &target)?;
-    } else {
-      if let Some(transformed_filename) = &mut f.transformed_file {
-        let fd = transformed_filename.as_file_mut();
-        fd.seek(SeekFrom::Start(0))?;
-        std::io::copy(fd, &mut asar)?;
-        std::fs::remove_file(transformed_filename)?;
-      } else {
-        let mut fd = File::open(&f.filename)?;
-        std::io::copy(&mut fd, &mut asar)?;
+  /// The staging archive, which packed file contents are written into at
+  /// the offset returned by `reserve_header`.
+  pub fn data(&self) -> &File {
+    self.archive.as_file()
+  }
+
+  /// Returns the staging path of an unpacked file, creating its parent
+  /// directories.
+  pub fn unpacked_path<T: AsRef<Path>>(&mut self, filename: T) -> Result<PathBuf> {
+    if self.unpacked.is_none() {
+      let mut builder = tempfile::Builder::new();
+      // like any new directory, once the umask is applied
+      #[cfg(not(target_os = "windows"))]
+      {
+        use std::os::unix::fs::PermissionsExt;
+        builder.permissions(std::fs::Permissions::from_mode(0o777));
       }
+      self.unpacked = Some(
+        builder
+          .prefix(".asar-unpacked-")
+          .tempdir_in(&self.dest_dir)?,
+      );
+    }
+    let target = self.unpacked.as_ref().unwrap().path().join(filename);
+    std::fs::create_dir_all(get_dir(&target))?;
+    Ok(target)
+  }
+
+  /// Writes the header in front of the packed contents, and replaces `dest`
+  /// and `dest.unpacked`. The old `dest.unpacked` is put back if `dest` can
+  /// not be replaced.
+  pub fn finish(self, header: &Node) -> Result<HeaderIntegrity> {
+    let (header, header_buf) = pickle_header(header)?;
+    if self
+      .header_size
+      .is_some_and(|size| size != header_buf.len())
+    {
+      return Err(
+        std::io::Error::new(
+          std::io::ErrorKind::InvalidData,
+          "the header does not fit the room made for it",
+        )
+        .into(),
+      );
     }
+
+    let mut size_pickle = Pickle::new();
+    size_pickle.write_uint32(header_buf.len() as u32);
+    let size_buf = size_pickle.to_vec();
+
+    let asar = self.archive;
+    let mut head = OffsetWriter::new(asar.as_file(), 0);
+    head.write_all(&size_buf)?;
+    head.write_all(&header_buf)?;
+    #[cfg(not(target_os = "windows"))]
+    if let Ok(metadata) = std::fs::metadata(&self.dest) {
+      asar.as_file().set_permissions(metadata.permissions())?;
+    }
+    asar.as_file().sync_all()?;
+
+    let unpacked_dest = PathBuf::from(self.dest.to_string_lossy().to_string() + ".unpacked");
+    let dest = self.dest;
+    replace_dir(self.unpacked, &unpacked_dest, move || {
+      asar.persist(dest).map_err(|e| e.error)?;
+      Ok(())
+    })?;
+
+    Ok(get_header_integrity(&header))
+  }
+}
+
+/// Returns the header JSON and its pickle.
+fn pickle_header(header: &Node) -> Result<(String, Vec<u8>)> {
+  let header = serde_json::to_string(header)?;
+  let mut header_pickle = Pickle::new();
+  header_pickle.write_string(&header);
+  let header_buf = header_pickle.to_vec();
+  Ok((header, header_buf))
+}
+
+/// Writes to `file` from `offset` on without using its cursor, so that
+/// several threads can fill different parts of one file.
+pub struct OffsetWriter<'a> {
+  file: &'a File,
+  offset: u64,
+}
+
+impl<'a> OffsetWriter<'a> {
+  pub fn new(file: &'a File, offset: u64) -> Self {
+    OffsetWriter { file, offset }
+  }
+}
+
+impl Write for OffsetWriter<'_> {
+  #[cfg(not(target_os = "windows"))]
+  fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+    let written = std::os::unix::fs::FileExt::write_at(self.file, buf, self.offset)?;
+    self.offset += written as u64;
+    Ok(written)
   }
 
+  #[cfg(target_os = "windows")]
+  fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+    // moves the cursor of the handle, which nothing else uses
+    let written = std::os::windows::fs::FileExt::seek_write(self.file, buf, self.offset)?;
+    self.offset += written as u64;
+    Ok(written)
+  }
+
+  fn flush(&mut self) -> std::io::Result<()> {
+    Ok(())
+  }
+}
+
+/// Moves `staging` to `dest` and calls `commit`. Whatever was at `dest` is
+/// put back if either fails, and removed otherwise.
+fn replace_dir<F>(staging: Option<TempDir>, dest: &Path, commit: F) -> Result<()>
+where
+  F: FnOnce() -> Result<()>,
+{
+  // moved into a directory of our own, so that nothing else is removed
+  let old = if dest.exists() {
+    let old = tempfile::Builder::new()
+      .prefix(".asar-unpacked-old-")
+      .tempdir_in(get_dir(dest))?;
+    std::fs::rename(dest, old.path().join("unpacked"))?;
+    Some(old)
+  } else {
+    None
+  };
+
+  // once renamed, dropping `staging` has nothing left to clean up
+  let result = match staging {
+    Some(staging) => std::fs::rename(staging.path(), dest).map_err(Error::from),
+    None => Ok(()),
+  }
+  .and_then(|()| commit());
+
+  if let Err(err) = result {
+    if dest.exists() {
+      std::fs::remove_dir_all(dest)?;
+    }
+    if let Some(old) = &old {
+      std::fs::rename(old.path().join("unpacked"), dest)?;
+    }
+    return Err(err);
+  }
+  if let Some(old) = old {
+    old.close()?;
+  }
   Ok(())
 }
@@ -0,0 +1,92 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::source::{ReadAt, ReadSeek};
+
+enum EntryFd<'a> {
+  Packed(&'a dyn ReadAt),
+  Unpacked(Box<dyn ReadSeek>),
+}
+
+/// A `Read + Seek` handle over the bytes of a single archive entry.
+///
+/// Positions are relative to the start of the entry, and reads never go
+/// past its size.
+pub struct EntryReader<'a> {
+  fd: EntryFd<'a>,
+  start: u64,
+  size: u64,
+  pos: u64,
+}
+
+impl<'a> EntryReader<'a> {
+  pub(crate) fn packed(fd: &'a dyn ReadAt, start: u64, size: u64) -> Self {
+    EntryReader {
+      fd: EntryFd::Packed(fd),
+      start,
+      size,
+      pos: 0,
+    }
+  }
+
+  pub(crate) fn unpacked(fd: Box<dyn ReadSeek>, size: u64) -> Self {
+    EntryReader {
+      fd: EntryFd::Unpacked(fd),
+      start: 0,
+      size,
+      pos: 0,
+    }
+  }
+
+  /// Size of the entry in bytes.
+  pub fn size(&self) -> u64 {
+    self.size
+  }
+}
+
+impl Read for EntryReader<'_> {
+  fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+    if self.pos >= self.size || buf.is_empty() {
+      return Ok(0);
+    }
+    let left = std::cmp::min(self.size - self.pos, buf.len() as u64) as usize;
+    let offset = self.start + self.pos;
+    let read_size = match &mut self.fd {
+      EntryFd::Packed(fd) => fd.read_at(&mut buf[0..left], offset)?,
+      EntryFd::Unpacked(fd) => {
+        fd.seek(SeekFrom::Start(offset))?;
+        fd.read(&mut buf[0..left])?
+      }
+    };
+    if read_size == 0 {
+      return Err(std::io::Error::new(
+        std::io::ErrorKind::UnexpectedEof,
+        "entry ends before its recorded size",
+      ));
+    }
+    self.pos += read_size as u64;
+    Ok(read_size)
+  }
+}
+
+impl Seek for EntryReader<'_> {
+  fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+    let (base, delta) = match pos {
+      SeekFrom::Start(n) => {
+        self.pos = n;
+        return Ok(n);
+      }
+      SeekFrom::End(n) => (self.size, n),
+      SeekFrom::Current(n) => (self.pos, n),
+    };
+    match base.checked_add_signed(delta) {
+      Some(n) => {
+        self.pos = n;
+        Ok(n)
+      }
+      None => Err(std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        "invalid seek to a negative or overflowing position",
+      )),
+    }
+  }
+}
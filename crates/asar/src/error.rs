@@ -23,6 +23,11 @@ pub enum ErrorStatus {
   Io,
   Json,
   Extraction,
+  IntegrityMismatch,
+  OutOfRange,
+  PathTraversal,
+  LinkLoop,
+  Transform,
 }
 
 #[derive(Debug)]
@@ -42,6 +47,11 @@ pub(crate) enum ErrorKind {
   Io(io::Error),
   Json(serde_json::Error),
   Extraction(Vec<Error>),
+  IntegrityMismatch(String),
+  OutOfRange(String),
+  PathTraversal(String),
+  LinkLoop(String),
+  Transform(String, io::Error),
 }
 
 impl Display for ErrorKind {
@@ -139,6 +149,47 @@ impl Display for ErrorKind {
         }
         Ok(())
       },
+      Self::IntegrityMismatch(asar_file_path) => {
+        write!(
+          f,
+          "{}::ErrorKind::IntegrityMismatch: \"{}\" does not match its integrity hash",
+          env!("CARGO_PKG_NAME"),
+          asar_file_path
+        )
+      }
+      Self::OutOfRange(asar_file_path) => {
+        write!(
+          f,
+          "{}::ErrorKind::OutOfRange: \"{}\": entry or requested bytes are out of range",
+          env!("CARGO_PKG_NAME"),
+          asar_file_path
+        )
+      }
+      Self::PathTraversal(asar_file_path) => {
+        write!(
+          f,
+          "{}::ErrorKind::PathTraversal: \"{}\" has an illegal name or links out of the archive",
+          env!("CARGO_PKG_NAME"),
+          asar_file_path
+        )
+      }
+      Self::LinkLoop(asar_file_path) => {
+        write!(
+          f,
+          "{}::ErrorKind::LinkLoop: \"{}\": too many levels of symbolic links",
+          env!("CARGO_PKG_NAME"),
+          asar_file_path
+        )
+      }
+      Self::Transform(asar_file_path, err) => {
+        write!(
+          f,
+          "{}::ErrorKind::Transform: \"{}\": unable to transform file: {}",
+          env!("CARGO_PKG_NAME"),
+          asar_file_path,
+          err
+        )
+      }
     }
   }
 }
@@ -170,6 +221,11 @@ impl ErrorImpl {
       ErrorKind::ParseInt(_) => ErrorStatus::ParseInt,
       ErrorKind::Io(_) => ErrorStatus::Io,
       ErrorKind::Json(_) => ErrorStatus::Json,
+      ErrorKind::IntegrityMismatch(_) => ErrorStatus::IntegrityMismatch,
+      ErrorKind::OutOfRange(_) => ErrorStatus::OutOfRange,
+      ErrorKind::PathTraversal(_) => ErrorStatus::PathTraversal,
+      ErrorKind::LinkLoop(_) => ErrorStatus::LinkLoop,
+      ErrorKind::Transform(..) => ErrorStatus::Transform,
     }
   }
 }
@@ -212,6 +268,7 @@ impl std::error::Error for Error {
     match self.kind() {
       ErrorKind::ParseInt(err) => Some(err),
       ErrorKind::Io(err) => Some(err),
+      ErrorKind::Transform(_, err) => Some(err),
       ErrorKind::Json(err) => Some(err),
       ErrorKind::Pattern(err) => Some(err),
       ErrorKind::Glob(err) => Some(err),
@@ -20,6 +20,11 @@ unsafe fn cstr_to_path(cstr: &CStr) -> &Path {
   str.as_ref()
 }
 
+/// # Safety
+///
+/// `archive` must be a valid NUL-terminated string. `buf_size` and `list_len`
+/// must be valid pointers. If `buf` is not null, it must point to `*buf_size`
+/// writable bytes and `list` must point to `*list_len` writable pointers.
 #[no_mangle]
 pub unsafe extern "C" fn asar_list_package(
   archive: *const c_char,
@@ -36,12 +41,10 @@ pub unsafe extern "C" fn asar_list_package(
     Err(err) => err.status(),
     Ok(l) => {
       if buf.is_null() {
-        let size = l
-          .iter()
-          .fold(0usize, |acc, e| return acc + e.as_bytes().len() + 1);
+        let size = l.iter().fold(0usize, |acc, e| acc + e.len() + 1);
         *buf_size = size;
         *list_len = l.len();
-        return ErrorStatus::Success;
+        ErrorStatus::Success
       } else {
         let size = *buf_size;
         let buffer = slice::from_raw_parts_mut(buf as *mut u8, size);
@@ -49,7 +52,7 @@ pub unsafe extern "C" fn asar_list_package(
         let mut pos: usize = 0usize;
         for (index, item) in l.iter().enumerate() {
           list_slice[index] = (buf as usize + pos) as *const c_char;
-          let strlen = item.as_bytes().len();
+          let strlen = item.len();
           let left = size - pos;
           if left <= strlen {
             buffer[pos..pos + left].copy_from_slice(&item.as_bytes()[0..left]);
@@ -63,12 +66,15 @@ pub unsafe extern "C" fn asar_list_package(
         }
         *buf_size = pos;
         *list_len = l.len();
-        return ErrorStatus::Success;
+        ErrorStatus::Success
       }
     }
   }
 }
 
+/// # Safety
+///
+/// `archive` and `dest` must be valid NUL-terminated strings.
 #[no_mangle]
 pub unsafe extern "C" fn asar_extract_all(
   archive: *const c_char,
@@ -86,6 +92,9 @@ pub unsafe extern "C" fn asar_extract_all(
   }
 }
 
+/// # Safety
+///
+/// `archive` and `dest` must be valid NUL-terminated strings.
 #[no_mangle]
 pub unsafe extern "C" fn asar_create_package(
   archive: *const c_char,
@@ -1,4 +1,5 @@
 use std::{
+  collections::VecDeque,
   ffi::OsStr,
   path::{Path, PathBuf, MAIN_SEPARATOR},
 };
@@ -11,6 +12,9 @@ use crate::{
 use path_absolutize::*;
 use pathdiff::diff_paths;
 
+/// Maximum number of links followed while resolving a path, like `ELOOP`.
+const MAX_LINK_DEPTH: usize = 40;
+
 pub fn get_dir<T: AsRef<Path>>(p: T) -> PathBuf {
   let path = p.as_ref();
   if path == Path::new("") {
@@ -46,6 +50,44 @@ pub fn relative<S: AsRef<Path>, D: AsRef<Path>>(src: S, dest: D) -> Result<PathB
   Ok(relative_path)
 }
 
+/// Returns whether `name` can be used as a single path component on disk.
+pub fn is_valid_entry_name(name: &str) -> bool {
+  if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\', '\0']) {
+    return false;
+  }
+  #[cfg(target_os = "windows")]
+  if name.contains(':') {
+    return false;
+  }
+  true
+}
+
+/// Returns whether a link target, relative to the archive root, stays inside
+/// the archive.
+pub fn is_valid_link_target(link: &str) -> bool {
+  if link.starts_with(['/', '\\']) || link.contains('\0') {
+    return false;
+  }
+  #[cfg(target_os = "windows")]
+  if link.contains(':') {
+    return false;
+  }
+  let mut depth: usize = 0;
+  for component in link.split(['/', '\\']) {
+    match component {
+      "" | "." => {}
+      ".." => {
+        if depth == 0 {
+          return false;
+        }
+        depth -= 1;
+      }
+      _ => depth += 1,
+    }
+  }
+  true
+}
+
 pub struct Filesystem {
   pub src: PathBuf,
   pub header: Node,
@@ -86,33 +128,13 @@ impl Filesystem {
     Ok(json)
   }
 
-  pub fn search_node_from_directory(&self, p: &str) -> Result<&Node> {
-    let mut json = &self.header;
-    let dirs: Vec<&str> = p.split(['\\', '/']).collect();
-    for dir in dirs {
-      if dir != "." {
-        match json {
-          Node::Directory(DirectoryNode { files, .. }) => {
-            json = files
-              .get(dir)
-              .ok_or_else(|| Error::new(ErrorKind::NoSuchEntry(p.to_owned())))?;
-          }
-          _ => {
-            return Err(Error::new(ErrorKind::ExpectDirNode(p.to_owned())));
-          }
-        };
-      }
-    }
-    Ok(json)
-  }
-
   pub fn search_dir_node_from_path_mut(&mut self, p: &str) -> Result<&mut DirectoryNode> {
     let path = relative(&self.src, Path::new(p));
     if let Ok(p) = path {
       if p == Path::new("") {
         return Ok(self.header.as_dir_node_mut().unwrap());
       }
-      let name = p.file_name().unwrap_or(&OsStr::new(""));
+      let name = p.file_name().unwrap_or(OsStr::new(""));
       let dir = &get_dir(p.clone()).to_string_lossy().to_string();
       let node = self.search_node_from_directory_mut(dir)?;
       match node {
@@ -141,15 +163,14 @@ impl Filesystem {
 
   pub fn insert(&mut self, p: &str, insert_node: Node) -> Result<()> {
     let p = relative(&self.src, Path::new(p))?;
-    let name = p.file_name().unwrap_or(&OsStr::new(""));
+    let name = p.file_name().unwrap_or(OsStr::new(""));
     let dir = &get_dir(p.clone()).to_string_lossy().to_string();
     let node = self.search_node_from_directory_mut(dir)?;
     match node {
       Node::Directory(n) => {
         let name_string = name.to_string_lossy().to_string();
-        if !insert_node.is_dir() || n.files.get(&name_string).is_none() {
-          n.files
-            .insert(name_string, insert_node);
+        if !insert_node.is_dir() || !n.files.contains_key(&name_string) {
+          n.files.insert(name_string, insert_node);
         }
         Ok(())
       }
@@ -177,7 +198,7 @@ impl Filesystem {
     fn fill_files_from_metadata(
       options: &ListOptions,
       list: &mut Vec<String>,
-      base_path: &PathBuf,
+      base_path: &Path,
       metadata: &Node,
     ) -> Result<()> {
       match metadata {
@@ -191,7 +212,7 @@ impl Filesystem {
               "pack  "
             };
             list.push(if options.is_pack {
-              (pack_state.to_owned() + &" : " + full_path.to_string_lossy().as_ref()).into()
+              pack_state.to_owned() + " : " + full_path.to_string_lossy().as_ref()
             } else {
               full_path.to_string_lossy().to_string()
             });
@@ -206,48 +227,112 @@ impl Filesystem {
       Ok(())
     }
     fill_files_from_metadata(
-      &options,
+      options,
       &mut files,
-      &PathBuf::from(MAIN_SEPARATOR.to_string()),
+      Path::new(&MAIN_SEPARATOR.to_string()),
       &self.header,
     )?;
     Ok(files)
   }
 
-  pub fn get_node(&self, p: &str) -> Result<&Node> {
-    let dirname = get_dir(&p);
-    let node = self.search_node_from_directory(dirname.to_string_lossy().as_ref())?;
-    let maybe_name = Path::new(p)
-      .file_name()
-      .or_else(|| Some(&OsStr::new("..")))
-      .and_then(|v| v.to_str());
-    if let Some(name) = maybe_name {
+  /// Checks that every entry name and link target in the header stays inside
+  /// the archive, so that it is safe to extract.
+  pub fn validate_paths(&self) -> Result<()> {
+    fn validate(base_path: &str, node: &Node) -> Result<()> {
       match node {
         Node::Directory(DirectoryNode { files, .. }) => {
-          return Ok(
-            files
-              .get(name)
-              .ok_or_else(|| Error::new(ErrorKind::NoSuchEntry(p.to_owned())))?,
-          );
+          for (name, child) in files {
+            let full_path = if base_path.is_empty() {
+              name.clone()
+            } else {
+              base_path.to_owned() + "/" + name
+            };
+            if !is_valid_entry_name(name) {
+              return Err(Error::new(ErrorKind::PathTraversal(full_path)));
+            }
+            validate(&full_path, child)?;
+          }
         }
-        _ => return Err(Error::new(ErrorKind::ExpectDirNode(p.to_owned()))),
+        Node::Link(LinkNode { link }) => {
+          if !is_valid_link_target(link) {
+            return Err(Error::new(ErrorKind::PathTraversal(base_path.to_owned())));
+          }
+        }
+        Node::File(_) => {}
       };
-    } else {
-      Ok(node)
+      Ok(())
     }
+    validate("", &self.header)
+  }
+
+  pub fn get_node(&self, p: &str) -> Result<&Node> {
+    Ok(self.resolve(p, false)?.1)
   }
 
   pub fn get_file(&self, p: &str, follow_links: Option<bool>) -> Result<&Node> {
-    let follow_links = follow_links.unwrap_or(true);
-    let info = self.get_node(&p)?;
-    if follow_links {
-      match info {
-        Node::Directory(_) => Ok(info),
-        Node::File(_) => Ok(info),
-        Node::Link(LinkNode { link }) => self.get_file(link, None),
+    Ok(self.resolve(p, follow_links.unwrap_or(true))?.1)
+  }
+
+  /// Resolves every link in `p` like `realpath(3)` and returns the canonical
+  /// path of the entry inside the archive.
+  pub fn realpath(&self, p: &str) -> Result<String> {
+    Ok(self.resolve(p, true)?.0)
+  }
+
+  /// Walks `p` component by component, replacing links in intermediate
+  /// components by their targets. The last component is only followed when
+  /// `follow_links` is set. Returns the resolved path and its node.
+  pub fn resolve(&self, p: &str, follow_links: bool) -> Result<(String, &Node)> {
+    fn components(p: &str) -> impl Iterator<Item = String> + '_ {
+      p.split(['\\', '/'])
+        .filter(|c| !c.is_empty() && *c != ".")
+        .map(|c| c.to_owned())
+    }
+
+    let mut pending: VecDeque<String> = components(p).collect();
+    let mut resolved: Vec<String> = vec![];
+    let mut node = &self.header;
+    let mut links: usize = 0;
+
+    while let Some(component) = pending.pop_front() {
+      if component == ".." {
+        if !node.is_dir() {
+          return Err(Error::new(ErrorKind::ExpectDirNode(p.to_owned())));
+        }
+        resolved.pop();
+        node = &self.header;
+        for name in resolved.iter() {
+          node = &node.as_dir_node().unwrap().files[name];
+        }
+        continue;
+      }
+
+      let child = match node {
+        Node::Directory(DirectoryNode { files, .. }) => files
+          .get(&component)
+          .ok_or_else(|| Error::new(ErrorKind::NoSuchEntry(p.to_owned())))?,
+        _ => return Err(Error::new(ErrorKind::ExpectDirNode(p.to_owned()))),
+      };
+
+      match child {
+        Node::Link(LinkNode { link }) if follow_links || !pending.is_empty() => {
+          // a cycle keeps expanding links until the limit is hit
+          links += 1;
+          if links > MAX_LINK_DEPTH {
+            return Err(Error::new(ErrorKind::LinkLoop(p.to_owned())));
+          }
+          // link targets are relative to the root of the archive
+          pending = components(link).chain(pending).collect();
+          resolved.clear();
+          node = &self.header;
+        }
+        _ => {
+          resolved.push(component);
+          node = child;
+        }
       }
-    } else {
-      return Ok(info);
     }
+
+    Ok((resolved.join("/"), node))
   }
 }
@@ -1,38 +1,41 @@
 use sha2::{Digest, Sha256};
-use std::{fs::File, io::Read, path::Path};
+use std::io::{Read, Write};
 
 use crate::error::Result;
-use crate::node::{Integrity, IntegrityAlgorithm};
+use crate::node::{HeaderIntegrity, Integrity, IntegrityAlgorithm};
 
 const BLOCK_SIZE: usize = 4 * 1024 * 1024;
 pub const BUFFER_SIZE: usize = 64 * 1024;
 
-pub fn get_file_integrity<T: AsRef<Path>>(path: T) -> Result<Integrity> {
-  let mut fd = File::open(path)?;
-  let mut file_hash = Sha256::new();
-  let mut blocks: Vec<String> = vec![];
-  let mut current_block_size: usize = 0;
-  let mut current_block_hash = Sha256::new();
-  let mut buffer = vec![0; BUFFER_SIZE];
+pub struct IntegrityHasher {
+  file_hash: Sha256,
+  blocks: Vec<String>,
+  current_block_size: usize,
+  current_block_hash: Sha256,
+}
 
-  loop {
-    let read_size = fd.read(&mut buffer)?;
-    if read_size == 0 {
-      blocks.push(hex::encode(current_block_hash.finalize()));
-      break;
+impl IntegrityHasher {
+  pub fn new() -> Self {
+    IntegrityHasher {
+      file_hash: Sha256::new(),
+      blocks: vec![],
+      current_block_size: 0,
+      current_block_hash: Sha256::new(),
     }
-    let mut chunk = &buffer[0..read_size];
+  }
 
-    file_hash.update(&chunk);
+  pub fn update(&mut self, data: &[u8]) {
+    let mut chunk = data;
+    self.file_hash.update(chunk);
 
     loop {
-      let diff_to_slice = std::cmp::min(BLOCK_SIZE - current_block_size, chunk.len());
-      current_block_size += diff_to_slice;
-      current_block_hash.update(&chunk[0..diff_to_slice]);
-      if current_block_size == BLOCK_SIZE {
-        blocks.push(hex::encode(current_block_hash.finalize()));
-        current_block_hash = Sha256::new();
-        current_block_size = 0;
+      let diff_to_slice = std::cmp::min(BLOCK_SIZE - self.current_block_size, chunk.len());
+      self.current_block_size += diff_to_slice;
+      self.current_block_hash.update(&chunk[0..diff_to_slice]);
+      if self.current_block_size == BLOCK_SIZE {
+        let block_hash = std::mem::take(&mut self.current_block_hash);
+        self.blocks.push(hex::encode(block_hash.finalize()));
+        self.current_block_size = 0;
       }
       if diff_to_slice < chunk.len() {
         chunk = &chunk[diff_to_slice..];
@@ -42,10 +45,106 @@ pub fn get_file_integrity<T: AsRef<Path>>(path: T) -> Result<Integrity> {
     }
   }
 
-  Ok(Integrity {
+  pub fn finalize(self) -> Integrity {
+    let mut blocks = self.blocks;
+    blocks.push(hex::encode(self.current_block_hash.finalize()));
+    Integrity {
+      algorithm: IntegrityAlgorithm::SHA256,
+      hash: hex::encode(self.file_hash.finalize()),
+      block_size: BLOCK_SIZE,
+      blocks,
+    }
+  }
+}
+
+/// An integrity shaped like the one of `size` bytes, for laying out a header
+/// before the content is hashed.
+pub fn placeholder_integrity(size: u64) -> Integrity {
+  let hash = "0".repeat(<Sha256 as Digest>::output_size() * 2);
+  Integrity {
     algorithm: IntegrityAlgorithm::SHA256,
-    hash: hex::encode(file_hash.finalize()),
+    hash: hash.clone(),
     block_size: BLOCK_SIZE,
-    blocks: blocks,
-  })
+    // a block is started after every full one, like `IntegrityHasher` does
+    blocks: vec![hash; (size / BLOCK_SIZE as u64) as usize + 1],
+  }
+}
+
+pub fn get_integrity<R: Read>(mut reader: R) -> Result<Integrity> {
+  let mut hasher = IntegrityHasher::new();
+  let mut buffer = vec![0; BUFFER_SIZE];
+
+  loop {
+    let read_size = reader.read(&mut buffer)?;
+    if read_size == 0 {
+      break;
+    }
+    hasher.update(&buffer[0..read_size]);
+  }
+
+  Ok(hasher.finalize())
+}
+
+/// Copies `reader` into `writer` and returns the number of bytes copied
+/// together with their integrity.
+pub fn copy_with_integrity<R: Read, W: Write + ?Sized>(
+  mut reader: R,
+  writer: &mut W,
+) -> Result<(u64, Integrity)> {
+  let mut hasher = IntegrityHasher::new();
+  let mut buffer = vec![0; BUFFER_SIZE];
+  let mut size: u64 = 0;
+
+  loop {
+    let read_size = reader.read(&mut buffer)?;
+    if read_size == 0 {
+      break;
+    }
+    hasher.update(&buffer[0..read_size]);
+    writer.write_all(&buffer[0..read_size])?;
+    size += read_size as u64;
+  }
+
+  Ok((size, hasher.finalize()))
+}
+
+pub fn get_header_integrity(header: &str) -> HeaderIntegrity {
+  HeaderIntegrity {
+    algorithm: IntegrityAlgorithm::SHA256,
+    hash: hex::encode(Sha256::digest(header.as_bytes())),
+  }
+}
+
+pub fn get_block_hash(data: &[u8]) -> String {
+  hex::encode(Sha256::digest(data))
+}
+
+pub fn matches(expected: &Integrity, actual: &Integrity) -> bool {
+  expected.hash == actual.hash && expected.blocks == actual.blocks
+}
+
+pub struct IntegrityReader<R: Read> {
+  inner: R,
+  hasher: IntegrityHasher,
+}
+
+impl<R: Read> IntegrityReader<R> {
+  pub fn new(inner: R) -> Self {
+    IntegrityReader {
+      inner,
+      hasher: IntegrityHasher::new(),
+    }
+  }
+
+  pub fn finalize(self) -> Integrity {
+    self.hasher.finalize()
+  }
+}
+
+impl<R: Read> Read for IntegrityReader<R> {
+  fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+    let read_size = self.inner.read(buf)?;
+    self.hasher.update(&buf[0..read_size]);
+    Ok(read_size)
+  }
 }
@@ -1,15 +1,37 @@
 use std::path::Path;
 
 mod asar;
+#[cfg(feature = "tokio")]
+mod async_file;
+mod builder;
+mod check;
+#[cfg(target_os = "linux")]
+mod copy;
 mod crawlfs;
 mod disk;
+mod entry;
 pub mod error;
 pub mod ffi;
 mod filesystem;
 mod integrity;
+mod locate;
 pub mod node;
+mod parallel;
+mod source;
+mod transform;
 
 pub use crate::asar::*;
+#[cfg(feature = "tokio")]
+pub use crate::async_file::{AsyncAsarFile, AsyncEntryReader};
+pub use crate::builder::{AsarBuilder, FileSource};
+pub use crate::check::Finding;
+pub use crate::entry::EntryReader;
+pub use crate::locate::{locate_archives, ArchiveLocation};
+pub use crate::source::{ReadSeek, UnpackedDir, UnpackedProvider};
+pub use crate::transform::{
+  command_transform, BuiltinTransform, TransformAdapter, TransformContext, TransformHook,
+  TransformOutput, TransformPredicate, TEXT_EXTENSIONS,
+};
 use error::Result;
 
 pub fn get_raw_header<T: AsRef<Path>>(archive: T) -> Result<(String, node::Node, usize)> {
@@ -17,12 +39,19 @@ pub fn get_raw_header<T: AsRef<Path>>(archive: T) -> Result<(String, node::Node,
   Ok((header, json_value, size))
 }
 
+/// Computes the SHA256 of the archive header, as expected by Electron's
+/// `ElectronAsarIntegrity` Info.plist / resource entry.
+pub fn get_header_integrity<T: AsRef<Path>>(archive: T) -> Result<node::HeaderIntegrity> {
+  let (header, _, _, _) = disk::read_archive_header(archive)?;
+  Ok(integrity::get_header_integrity(&header))
+}
+
 pub fn stat_file<T: AsRef<Path>>(
   archive: T,
   filename: &str,
   follow_links: Option<bool>,
 ) -> Result<node::Node> {
-  let mut asar = AsarFile::open(archive)?;
+  let asar = AsarFile::open(archive)?;
   Ok(asar.stat_file(filename, follow_links)?.clone())
 }
 
@@ -39,11 +68,34 @@ pub fn list_package_with_options<T: AsRef<Path>>(
 }
 
 pub fn extract_file<T: AsRef<Path>>(archive: T, filename: &str) -> error::Result<Vec<u8>> {
-  let mut asar = AsarFile::open(archive)?;
-  Ok(asar.read_file(filename)?)
+  let asar = AsarFile::open(archive)?;
+  asar.read_file(filename)
+}
+
+pub fn verify_package<T: AsRef<Path>>(archive: T) -> error::Result<Vec<IntegrityMismatch>> {
+  let asar = AsarFile::open(archive)?;
+  asar.verify()
+}
+
+pub fn check_package<T: AsRef<Path>>(archive: T) -> error::Result<Vec<Finding>> {
+  let asar = AsarFile::open(archive)?;
+  Ok(asar.check())
+}
+
+pub fn locate_package<T: AsRef<Path>>(file: T) -> error::Result<Vec<ArchiveLocation>> {
+  let mut fd = std::fs::File::open(file)?;
+  locate_archives(&mut fd)
 }
 
 pub fn extract_all<T: AsRef<Path>, U: AsRef<Path>>(archive: T, dest: U) -> error::Result<()> {
-  let mut asar = AsarFile::open(archive)?;
-  Ok(asar.extract_all(dest)?)
+  extract_all_with_options(archive, dest, &ExtractOptions::new())
+}
+
+pub fn extract_all_with_options<T: AsRef<Path>, U: AsRef<Path>>(
+  archive: T,
+  dest: U,
+  options: &ExtractOptions,
+) -> error::Result<()> {
+  let asar = AsarFile::open(archive)?;
+  asar.extract_all_with_options(dest, options)
 }
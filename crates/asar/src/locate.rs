@@ -0,0 +1,103 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::{
+  disk::read_header,
+  error::Result,
+  integrity::BUFFER_SIZE,
+  node::{DirectoryNode, FileNode, Node},
+};
+
+// size pickle (8 bytes), header pickle payload size and string length (8
+// bytes), and the opening brace of the header JSON
+const SIGNATURE_SIZE: usize = 17;
+
+/// An archive found inside a larger file by `locate_archives`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveLocation {
+  /// Where the archive starts.
+  pub offset: u64,
+  pub header_size: u64,
+  /// The smallest length that covers the header and every packed file.
+  pub len: u64,
+}
+
+/// Scans `reader` for asar headers and returns every offset at which a
+/// complete archive could be opened with `AsarFile::open_at`.
+pub fn locate_archives<R: Read + Seek + ?Sized>(reader: &mut R) -> Result<Vec<ArchiveLocation>> {
+  let total = reader.seek(SeekFrom::End(0))?;
+  reader.seek(SeekFrom::Start(0))?;
+
+  let mut candidates: Vec<u64> = vec![];
+  let mut buffer = vec![0u8; BUFFER_SIZE];
+  // file offset of `buffer[0]`
+  let mut base: u64 = 0;
+  let mut filled: usize = 0;
+  loop {
+    let read_size = reader.read(&mut buffer[filled..])?;
+    if read_size == 0 {
+      break;
+    }
+    filled += read_size;
+    let mut i = 0;
+    while i + SIGNATURE_SIZE <= filled {
+      if is_plausible_header(&buffer[i..i + SIGNATURE_SIZE]) {
+        candidates.push(base + i as u64);
+      }
+      i += 1;
+    }
+    // keep the tail, a header may start there
+    buffer.copy_within(i..filled, 0);
+    base += i as u64;
+    filled -= i;
+  }
+
+  let mut locations: Vec<ArchiveLocation> = vec![];
+  for offset in candidates {
+    let (_, header, header_size, _) = match read_header(reader, offset, None) {
+      Ok(parsed) => parsed,
+      Err(_) => continue,
+    };
+    let len = match data_end(&header).checked_add(8 + header_size as u64) {
+      Some(len) if len <= total - offset => len,
+      _ => continue,
+    };
+    locations.push(ArchiveLocation {
+      offset,
+      header_size: header_size as u64,
+      len,
+    });
+  }
+  Ok(locations)
+}
+
+fn read_u32(bytes: &[u8]) -> u64 {
+  u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as u64
+}
+
+fn is_plausible_header(bytes: &[u8]) -> bool {
+  let size_payload = read_u32(&bytes[0..4]);
+  let header_size = read_u32(&bytes[4..8]);
+  let header_payload = read_u32(&bytes[8..12]);
+  let string_len = read_u32(&bytes[12..16]);
+  size_payload == 4
+    && header_size == header_payload + 4
+    && header_payload == string_len.div_ceil(4) * 4 + 4
+    && bytes[16] == b'{'
+}
+
+fn data_end(node: &Node) -> u64 {
+  match node {
+    Node::Directory(DirectoryNode { files, .. }) => files.values().map(data_end).max().unwrap_or(0),
+    Node::File(FileNode {
+      size,
+      offset: Some(offset),
+      unpacked,
+      ..
+    }) if !unpacked.unwrap_or(false) => offset
+      .parse::<u64>()
+      .ok()
+      .and_then(|offset| offset.checked_add(*size as u64))
+      .unwrap_or(u64::MAX),
+    _ => 0,
+  }
+}
@@ -1,7 +1,8 @@
 use anyhow::Result;
 use asar_rs::{
-  create_package_with_options, extract_all, list_package_with_options, AsarFile, CreateOptions,
-  ListOptions,
+  check_package, command_transform, create_package_with_options, extract_all_with_options,
+  get_header_integrity, list_package_with_options, locate_package, verify_package, AsarFile,
+  BuiltinTransform, CreateOptions, ExtractOptions, ListOptions,
 };
 use clap::{
   arg, command,
@@ -27,6 +28,22 @@ pub fn main() -> Result<()> {
             .required(false),
         )
         .arg(arg!(--"exclude-hidden" "exclude hidden files").action(ArgAction::SetTrue))
+        .arg(
+          arg!(--builtin <name> "apply a built-in transform, can be repeated")
+            .value_parser(BuiltinTransform::ALL.map(|builtin| builtin.name()))
+            .action(ArgAction::Append)
+            .required(false),
+        )
+        .arg(
+          arg!(--transform <spec> "pipe files matching <glob> through <command>, as <glob>=<command>")
+            .action(ArgAction::Append)
+            .required(false),
+        )
+        .arg(
+          arg!(-j --threads <count> "number of files hashed at once, 0 for one per CPU")
+            .value_parser(clap::value_parser!(usize))
+            .required(false),
+        )
         .arg(arg!(<dir>))
         .arg(arg!(<output>)),
     )
@@ -52,9 +69,37 @@ pub fn main() -> Result<()> {
       Command::new("extract")
         .alias("e")
         .about("extract archive")
+        .arg(
+          arg!(-j --threads <count> "number of files extracted at once, 0 for one per CPU")
+            .value_parser(clap::value_parser!(usize))
+            .required(false),
+        )
         .arg(arg!(<archive>))
         .arg(arg!(<dest>)),
     )
+    .subcommand(
+      Command::new("verify")
+        .alias("v")
+        .about("verify the integrity of every file in archive")
+        .arg(arg!(<archive>)),
+    )
+    .subcommand(
+      Command::new("integrity")
+        .about("print the header hash of archives for ElectronAsarIntegrity")
+        .arg(arg!(--plist "print an Info.plist fragment instead of JSON").action(ArgAction::SetTrue))
+        .arg(arg!(<archive> ...)),
+    )
+    .subcommand(
+      Command::new("check")
+        .about("check the structure of archive without extracting it")
+        .arg(arg!(--json "print findings as JSON").action(ArgAction::SetTrue))
+        .arg(arg!(<archive>)),
+    )
+    .subcommand(
+      Command::new("locate")
+        .about("find archives embedded in a file")
+        .arg(arg!(<file>)),
+    )
     .try_get_matches()
     .unwrap_or_else(|e| {
       match e.kind() {
@@ -93,13 +138,28 @@ pub fn main() -> Result<()> {
       let dir = sub_match.get_one::<String>("dir").unwrap();
       let output = sub_match.get_one::<String>("output").unwrap();
       let mut options = CreateOptions::new();
-      options.unpack = sub_match.get_one::<String>("unpack").map(|v| v.clone());
-      options.unpack_dir = sub_match.get_one::<String>("unpack-dir").map(|v| v.clone());
-      options.ordering = sub_match
-        .get_one::<std::path::PathBuf>("ordering")
-        .map(|v| v.clone());
+      options.unpack = sub_match.get_one::<String>("unpack").cloned();
+      options.unpack_dir = sub_match.get_one::<String>("unpack-dir").cloned();
+      options.ordering = sub_match.get_one::<std::path::PathBuf>("ordering").cloned();
       options.dot = sub_match.get_one::<bool>("exclude-hidden").map(|v| !v);
-      create_package_with_options(&dir, &output, &options)?;
+      if let Some(threads) = sub_match.get_one::<usize>("threads") {
+        options.threads = *threads;
+      }
+      for name in sub_match.get_many::<String>("builtin").unwrap_or_default() {
+        options.builtins.extend(BuiltinTransform::from_name(name));
+      }
+      for spec in sub_match
+        .get_many::<String>("transform")
+        .unwrap_or_default()
+      {
+        let Some((pattern, command)) = spec.split_once('=') else {
+          anyhow::bail!("invalid transform '{}', expected <glob>=<command>", spec);
+        };
+        options
+          .transforms
+          .push(command_transform(pattern, command)?);
+      }
+      create_package_with_options(dir, output, &options)?;
     }
     Some(("list", sub_match)) => {
       let archive = sub_match.get_one::<String>("archive").unwrap();
@@ -113,9 +173,9 @@ pub fn main() -> Result<()> {
     Some(("extract-file", sub_match)) => {
       let archive = sub_match.get_one::<String>("archive").unwrap();
       let filename = sub_match.get_one::<String>("filename").unwrap();
-      let mut asar = AsarFile::open(archive)?;
+      let asar = AsarFile::open(archive)?;
       asar.extract_file(
-        &filename,
+        filename,
         std::path::PathBuf::from(filename)
           .file_name()
           .unwrap()
@@ -126,7 +186,86 @@ pub fn main() -> Result<()> {
     Some(("extract", sub_match)) => {
       let archive = sub_match.get_one::<String>("archive").unwrap();
       let dest = sub_match.get_one::<String>("dest").unwrap();
-      extract_all(archive, dest)?;
+      let mut options = ExtractOptions::new();
+      if let Some(threads) = sub_match.get_one::<usize>("threads") {
+        options.threads = *threads;
+      }
+      extract_all_with_options(archive, dest, &options)?;
+    }
+    Some(("verify", sub_match)) => {
+      let archive = sub_match.get_one::<String>("archive").unwrap();
+      let mismatches = verify_package(archive)?;
+      for mismatch in mismatches.iter() {
+        match &mismatch.error {
+          Some(error) => println!("{}: unable to verify file: {}", mismatch.path, error),
+          None => println!(
+            "{}: integrity mismatch (expected {}, actual {})",
+            mismatch.path, mismatch.expected, mismatch.actual
+          ),
+        }
+      }
+      if !mismatches.is_empty() {
+        anyhow::bail!("{} file(s) failed integrity verification", mismatches.len());
+      }
+    }
+    Some(("integrity", sub_match)) => {
+      let archives = sub_match.get_many::<String>("archive").unwrap();
+      let mut integrities = serde_json::Map::new();
+      for archive in archives {
+        integrities.insert(
+          archive.clone(),
+          serde_json::to_value(get_header_integrity(archive)?)?,
+        );
+      }
+      if *sub_match.get_one::<bool>("plist").unwrap_or(&false) {
+        println!("<key>ElectronAsarIntegrity</key>");
+        println!("<dict>");
+        for (archive, integrity) in integrities.iter() {
+          println!("  <key>{}</key>", escape_xml(&plist_key(archive)));
+          println!("  <dict>");
+          println!("    <key>algorithm</key>");
+          println!(
+            "    <string>{}</string>",
+            integrity["algorithm"].as_str().unwrap()
+          );
+          println!("    <key>hash</key>");
+          println!(
+            "    <string>{}</string>",
+            integrity["hash"].as_str().unwrap()
+          );
+          println!("  </dict>");
+        }
+        println!("</dict>");
+      } else {
+        println!("{}", serde_json::to_string_pretty(&integrities)?);
+      }
+    }
+    Some(("check", sub_match)) => {
+      let archive = sub_match.get_one::<String>("archive").unwrap();
+      let findings = check_package(archive)?;
+      if *sub_match.get_one::<bool>("json").unwrap_or(&false) {
+        println!("{}", serde_json::to_string_pretty(&findings)?);
+      } else {
+        for finding in findings.iter() {
+          println!("{}", finding);
+        }
+      }
+      if !findings.is_empty() {
+        anyhow::bail!("{} problem(s) found in {}", findings.len(), archive);
+      }
+    }
+    Some(("locate", sub_match)) => {
+      let file = sub_match.get_one::<String>("file").unwrap();
+      let locations = locate_package(file)?;
+      if locations.is_empty() {
+        anyhow::bail!("no archive found in {}", file);
+      }
+      for location in locations.iter() {
+        println!(
+          "offset {} length {} (header {} bytes)",
+          location.offset, location.len, location.header_size
+        );
+      }
     }
     _ => {
       unreachable!();
@@ -135,3 +274,19 @@ pub fn main() -> Result<()> {
 
   Ok(())
 }
+
+/// Electron looks archives up by their path relative to `Contents`, and
+/// they live in `Contents/Resources`.
+fn plist_key(archive: &str) -> String {
+  let name = std::path::Path::new(archive)
+    .file_name()
+    .map(|name| name.to_string_lossy())
+    .unwrap_or_default();
+  format!("Resources/{}", name)
+}
+
+fn escape_xml(s: &str) -> String {
+  s.replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+}
@@ -16,6 +16,14 @@ pub struct Integrity {
   pub blocks: Vec<String>,
 }
 
+/// SHA256 of the header JSON string, as checked by Electron's
+/// `ElectronAsarIntegrity` fuse.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct HeaderIntegrity {
+  pub algorithm: IntegrityAlgorithm,
+  pub hash: String,
+}
+
 #[derive(Default, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct FileNode {
@@ -65,27 +73,15 @@ pub enum Node {
 
 impl Node {
   pub fn is_file(&self) -> bool {
-    if let Self::File(..) = self {
-      true
-    } else {
-      false
-    }
+    matches!(self, Self::File(..))
   }
 
   pub fn is_dir(&self) -> bool {
-    if let Self::Directory(..) = self {
-      true
-    } else {
-      false
-    }
+    matches!(self, Self::Directory(..))
   }
 
   pub fn is_link(&self) -> bool {
-    if let Self::Link(..) = self {
-      true
-    } else {
-      false
-    }
+    matches!(self, Self::Link(..))
   }
 
   pub fn as_dir_node(&self) -> Option<&DirectoryNode> {
@@ -0,0 +1,53 @@
+use std::sync::{
+  atomic::{AtomicUsize, Ordering},
+  Mutex, PoisonError,
+};
+
+/// Resolves a user supplied thread count, where 0 means one thread per
+/// available CPU.
+pub fn thread_count(threads: usize) -> usize {
+  if threads == 0 {
+    std::thread::available_parallelism()
+      .map(|n| n.get())
+      .unwrap_or(1)
+  } else {
+    threads
+  }
+}
+
+/// Applies `f` to every item on up to `threads` scoped threads and returns
+/// the results in the order of `items`.
+pub fn map<T, R, F>(items: &[T], threads: usize, f: F) -> Vec<R>
+where
+  T: Sync,
+  R: Send,
+  F: Fn(&T) -> R + Sync,
+{
+  let threads = std::cmp::min(thread_count(threads), items.len());
+  if threads <= 1 {
+    return items.iter().map(f).collect();
+  }
+
+  let next = AtomicUsize::new(0);
+  let results: Mutex<Vec<Option<R>>> = Mutex::new(items.iter().map(|_| None).collect());
+  std::thread::scope(|scope| {
+    for _ in 0..threads {
+      scope.spawn(|| loop {
+        let index = next.fetch_add(1, Ordering::Relaxed);
+        let item = match items.get(index) {
+          Some(item) => item,
+          None => break,
+        };
+        let result = f(item);
+        results.lock().unwrap_or_else(PoisonError::into_inner)[index] = Some(result);
+      });
+    }
+  });
+
+  results
+    .into_inner()
+    .unwrap_or_else(PoisonError::into_inner)
+    .into_iter()
+    .map(|result| result.expect("every item is processed"))
+    .collect()
+}
@@ -0,0 +1,177 @@
+use std::{
+  fs::File,
+  io::{Read, Seek, SeekFrom},
+  path::{Path, PathBuf},
+  sync::{Mutex, PoisonError},
+};
+
+use memmap2::Mmap;
+
+/// Anything an archive can be read from.
+pub trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek + ?Sized> ReadSeek for T {}
+
+/// A source that can be read at any offset through a shared reference, so
+/// that several threads can read one archive at once.
+pub(crate) trait ReadAt: Send + Sync {
+  fn read_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<usize>;
+
+  fn len(&self) -> std::io::Result<u64>;
+
+  /// The whole source, if it is already in memory.
+  fn as_slice(&self) -> Option<&[u8]> {
+    None
+  }
+
+  /// The underlying file, for copies that the kernel can do by itself.
+  #[cfg(target_os = "linux")]
+  fn as_file(&self) -> Option<&File> {
+    None
+  }
+}
+
+impl ReadAt for File {
+  #[cfg(not(target_os = "windows"))]
+  fn read_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+    std::os::unix::fs::FileExt::read_at(self, buf, offset)
+  }
+
+  #[cfg(target_os = "windows")]
+  fn read_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+    // moves the cursor of the handle, but nothing reads from the cursor
+    std::os::windows::fs::FileExt::seek_read(self, buf, offset)
+  }
+
+  fn len(&self) -> std::io::Result<u64> {
+    Ok(self.metadata()?.len())
+  }
+
+  #[cfg(target_os = "linux")]
+  fn as_file(&self) -> Option<&File> {
+    Some(self)
+  }
+}
+
+fn read_slice_at(data: &[u8], buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+  let start = std::cmp::min(offset, data.len() as u64) as usize;
+  let read_size = std::cmp::min(buf.len(), data.len() - start);
+  buf[..read_size].copy_from_slice(&data[start..start + read_size]);
+  Ok(read_size)
+}
+
+impl ReadAt for Vec<u8> {
+  fn read_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+    read_slice_at(self, buf, offset)
+  }
+
+  fn len(&self) -> std::io::Result<u64> {
+    Ok(Vec::len(self) as u64)
+  }
+
+  fn as_slice(&self) -> Option<&[u8]> {
+    Some(self)
+  }
+}
+
+impl ReadAt for Mmap {
+  fn read_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+    read_slice_at(self, buf, offset)
+  }
+
+  fn len(&self) -> std::io::Result<u64> {
+    Ok(<[u8]>::len(self) as u64)
+  }
+
+  fn as_slice(&self) -> Option<&[u8]> {
+    Some(self)
+  }
+}
+
+/// Shares a `Read + Seek` source that has no positional reads by taking
+/// turns on its cursor.
+pub(crate) struct SeekSource<R: Read + Seek + Send> {
+  // every read seeks first, so a poisoned lock is still usable
+  inner: Mutex<R>,
+}
+
+impl<R: Read + Seek + Send> SeekSource<R> {
+  pub fn new(inner: R) -> Self {
+    SeekSource {
+      inner: Mutex::new(inner),
+    }
+  }
+}
+
+impl<R: Read + Seek + Send> ReadAt for SeekSource<R> {
+  fn read_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+    let mut inner = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+    inner.seek(SeekFrom::Start(offset))?;
+    inner.read(buf)
+  }
+
+  fn len(&self) -> std::io::Result<u64> {
+    let mut inner = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+    inner.seek(SeekFrom::End(0))
+  }
+}
+
+/// Supplies the files that are stored next to an archive instead of inside
+/// it, for archives that are not opened from a path.
+pub trait UnpackedProvider: Send + Sync {
+  /// Opens the unpacked file at the archive-relative `path` and returns it
+  /// together with its size.
+  fn open(&self, path: &str) -> std::io::Result<(Box<dyn ReadSeek>, u64)>;
+
+  fn exists(&self, path: &str) -> bool {
+    self.open(path).is_ok()
+  }
+
+  /// Copies the unpacked file at `path` to `dest` on disk.
+  fn copy_to(&self, path: &str, dest: &Path) -> std::io::Result<u64> {
+    let (mut reader, _) = self.open(path)?;
+    std::io::copy(&mut reader, &mut File::create(dest)?)
+  }
+
+  /// Permissions that extracted copies of the file at `path` get, if the
+  /// provider knows of any.
+  fn permissions(&self, _path: &str) -> Option<std::fs::Permissions> {
+    None
+  }
+}
+
+/// The `.unpacked` directory of an archive on disk.
+pub struct UnpackedDir {
+  dir: PathBuf,
+}
+
+impl UnpackedDir {
+  pub fn new<T: AsRef<Path>>(dir: T) -> Self {
+    UnpackedDir {
+      dir: dir.as_ref().to_path_buf(),
+    }
+  }
+}
+
+impl UnpackedProvider for UnpackedDir {
+  fn open(&self, path: &str) -> std::io::Result<(Box<dyn ReadSeek>, u64)> {
+    let fd = File::open(self.dir.join(path))?;
+    // unpacked files live on disk, so their actual length is authoritative
+    let size = fd.metadata()?.len();
+    Ok((Box::new(fd), size))
+  }
+
+  fn exists(&self, path: &str) -> bool {
+    self.dir.join(path).is_file()
+  }
+
+  fn copy_to(&self, path: &str, dest: &Path) -> std::io::Result<u64> {
+    std::fs::copy(self.dir.join(path), dest)
+  }
+
+  fn permissions(&self, path: &str) -> Option<std::fs::Permissions> {
+    std::fs::metadata(self.dir.join(path))
+      .ok()
+      .map(|metadata| metadata.permissions())
+  }
+}
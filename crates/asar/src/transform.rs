@@ -0,0 +1,446 @@
+use std::{
+  fs::{File, Metadata},
+  io::{Cursor, Read, Seek, SeekFrom},
+  process::{Command, Stdio},
+};
+
+use crate::{asar::minimatch, error::Result};
+
+/// What a transform is told about the file it may rewrite.
+pub struct TransformContext<'a> {
+  /// Path of the file inside the archive, with `/` separators.
+  pub path: &'a str,
+  pub metadata: &'a Metadata,
+  /// Whether the file is stored in `.unpacked` instead of the archive.
+  pub unpacked: bool,
+}
+
+/// Turns a reader over the current content of a file into a reader over its
+/// new content.
+pub type TransformAdapter = Box<dyn FnOnce(Box<dyn Read>) -> Box<dyn Read>>;
+
+/// Decides from the content of a file whether it is left out.
+pub type TransformPredicate = Box<dyn FnOnce(&[u8]) -> bool>;
+
+/// The new content of a transformed file.
+pub enum TransformOutput {
+  /// Streams the new content, so large files never sit in memory.
+  Adapter(TransformAdapter),
+  /// Replaces the content without reading it.
+  Bytes(Vec<u8>),
+  /// Leaves the file out of the archive.
+  Remove,
+  /// Leaves the file out of the archive if its content, which is read into
+  /// memory to decide, is accepted by the predicate.
+  RemoveIf(TransformPredicate),
+}
+
+impl TransformOutput {
+  pub fn adapter<F>(f: F) -> Self
+  where
+    F: FnOnce(Box<dyn Read>) -> Box<dyn Read> + 'static,
+  {
+    TransformOutput::Adapter(Box::new(f))
+  }
+}
+
+/// Decides whether and how a file is rewritten while packing. Returning
+/// `Ok(None)` keeps the file as it is.
+///
+/// Hooks are called from several threads when `CreateOptions::threads` is
+/// not 1.
+pub type TransformHook =
+  Box<dyn Fn(&TransformContext) -> std::io::Result<Option<TransformOutput>> + Send + Sync>;
+
+/// What became of a file after every transform had its turn.
+pub(crate) enum Applied {
+  Unchanged,
+  Content(Box<dyn Read>),
+  Removed,
+}
+
+/// Applies `outputs` in order, each one over the output of the previous one.
+pub(crate) fn apply<I>(outputs: I, content: Box<dyn Read>) -> std::io::Result<Applied>
+where
+  I: IntoIterator<Item = std::io::Result<Option<TransformOutput>>>,
+{
+  let mut content = content;
+  let mut transformed = false;
+  for output in outputs {
+    content = match output? {
+      Some(TransformOutput::Adapter(adapter)) => adapter(content),
+      Some(TransformOutput::Bytes(bytes)) => Box::new(Cursor::new(bytes)),
+      Some(TransformOutput::Remove) => return Ok(Applied::Removed),
+      Some(TransformOutput::RemoveIf(predicate)) => {
+        let mut buffer = vec![];
+        content.read_to_end(&mut buffer)?;
+        if predicate(&buffer) {
+          return Ok(Applied::Removed);
+        }
+        // the content was only looked at
+        content = Box::new(Cursor::new(buffer));
+        continue;
+      }
+      None => continue,
+    };
+    transformed = true;
+  }
+  Ok(if transformed {
+    Applied::Content(content)
+  } else {
+    Applied::Unchanged
+  })
+}
+
+/// Pipes every file matching `pattern` through the shell `command` and
+/// packs what it prints. A pattern without `/` is matched against file
+/// names, like `CreateOptions::unpack`.
+pub fn command_transform(pattern: &str, command: &str) -> Result<TransformHook> {
+  glob::Pattern::new(pattern)?;
+  let pattern = pattern.to_owned();
+  let command = command.to_owned();
+  Ok(Box::new(move |context: &TransformContext| {
+    if !minimatch(context.path, &pattern, !pattern.contains('/')).map_err(std::io::Error::other)? {
+      return Ok(None);
+    }
+    let command = command.clone();
+    Ok(Some(TransformOutput::adapter(move |content| {
+      deferred(move || Ok(Box::new(run_command(&command, content)?)))
+    })))
+  }))
+}
+
+type Produce = Box<dyn FnOnce() -> std::io::Result<Box<dyn Read>>>;
+
+/// Produces its content on the first read, so that adapters which have to
+/// see the whole input can still report errors through `Read`.
+struct Deferred {
+  produce: Option<Produce>,
+  content: Box<dyn Read>,
+}
+
+fn deferred<F>(produce: F) -> Box<dyn Read>
+where
+  F: FnOnce() -> std::io::Result<Box<dyn Read>> + 'static,
+{
+  Box::new(Deferred {
+    produce: Some(Box::new(produce)),
+    content: Box::new(std::io::empty()),
+  })
+}
+
+impl Read for Deferred {
+  fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+    if let Some(produce) = self.produce.take() {
+      self.content = produce()?;
+    }
+    self.content.read(buf)
+  }
+}
+
+fn run_command(command: &str, mut content: Box<dyn Read>) -> std::io::Result<File> {
+  #[cfg(not(target_os = "windows"))]
+  let (shell, flag) = ("sh", "-c");
+  #[cfg(target_os = "windows")]
+  let (shell, flag) = ("cmd", "/C");
+
+  let mut child = Command::new(shell)
+    .arg(flag)
+    .arg(command)
+    .stdin(Stdio::piped())
+    .stdout(Stdio::piped())
+    .spawn()?;
+
+  // drain stdout on another thread, or a command that writes more than the
+  // pipe holds would never read the rest of its input
+  let mut stdout = child.stdout.take().expect("stdout is piped");
+  let output = std::thread::spawn(move || -> std::io::Result<File> {
+    let mut output = tempfile::tempfile()?;
+    std::io::copy(&mut stdout, &mut output)?;
+    output.seek(SeekFrom::Start(0))?;
+    Ok(output)
+  });
+
+  let mut stdin = child.stdin.take().expect("stdin is piped");
+  match std::io::copy(&mut content, &mut stdin) {
+    // the command does not need all of its input
+    Err(err) if err.kind() == std::io::ErrorKind::BrokenPipe => {}
+    result => {
+      result?;
+    }
+  }
+  drop(stdin);
+
+  let status = child.wait()?;
+  let output = output
+    .join()
+    .map_err(|_| std::io::Error::other("unable to read the output of the command"))??;
+  if !status.success() {
+    return Err(std::io::Error::other(format!(
+      "`{}` failed with {}",
+      command, status
+    )));
+  }
+  Ok(output)
+}
+
+/// Ready-made transforms for common packaging chores, switched on with
+/// `CreateOptions::builtins`. They run before `CreateOptions::transforms`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuiltinTransform {
+  /// Removes the whitespace between the tokens of `.json` files. Files that
+  /// are not valid JSON are packed as they are.
+  MinifyJson,
+  /// Turns CRLF line endings into LF in text files, as told by
+  /// `TEXT_EXTENSIONS`.
+  NormalizeLineEndings,
+  /// Removes the trailing `sourceMappingURL` comment from scripts and style
+  /// sheets, and leaves the `.map` files that are source maps out of the
+  /// archive.
+  StripSourceMaps,
+  /// Removes `devDependencies` and `scripts` from `package.json` files,
+  /// keeping the rest of the file as it is.
+  StripPackageJson,
+}
+
+/// Extensions of the files `BuiltinTransform::NormalizeLineEndings` touches.
+pub const TEXT_EXTENSIONS: &[&str] = &[
+  "cjs", "css", "htm", "html", "js", "json", "jsx", "md", "mjs", "svg", "ts", "tsx", "txt", "xml",
+  "yaml", "yml",
+];
+
+const SCRIPT_EXTENSIONS: &[&str] = &["cjs", "css", "js", "mjs"];
+
+const PACKAGE_JSON_DEV_FIELDS: &[&str] = &["devDependencies", "scripts"];
+
+impl BuiltinTransform {
+  pub const ALL: [BuiltinTransform; 4] = [
+    BuiltinTransform::MinifyJson,
+    BuiltinTransform::NormalizeLineEndings,
+    BuiltinTransform::StripSourceMaps,
+    BuiltinTransform::StripPackageJson,
+  ];
+
+  /// The name used on the command line.
+  pub fn name(self) -> &'static str {
+    match self {
+      BuiltinTransform::MinifyJson => "minify-json",
+      BuiltinTransform::NormalizeLineEndings => "crlf-to-lf",
+      BuiltinTransform::StripSourceMaps => "strip-source-maps",
+      BuiltinTransform::StripPackageJson => "strip-package-json",
+    }
+  }
+
+  pub fn from_name(name: &str) -> Option<Self> {
+    Self::ALL.into_iter().find(|builtin| builtin.name() == name)
+  }
+
+  /// This transform as a hook, to be combined with others in
+  /// `CreateOptions::transforms`.
+  pub fn hook(self) -> TransformHook {
+    Box::new(move |context: &TransformContext| Ok(self.output(context)))
+  }
+
+  pub(crate) fn output(self, context: &TransformContext) -> Option<TransformOutput> {
+    let name = context.path.rsplit('/').next().unwrap_or(context.path);
+    let extension = name
+      .rsplit_once('.')
+      .map(|(_, extension)| extension.to_ascii_lowercase())
+      .unwrap_or_default();
+    let extension = extension.as_str();
+    match self {
+      BuiltinTransform::MinifyJson if extension == "json" => {
+        Some(buffered(|content| Ok(minify_json(content))))
+      }
+      BuiltinTransform::NormalizeLineEndings if TEXT_EXTENSIONS.contains(&extension) => {
+        Some(buffered(|content| Ok(crlf_to_lf(&content))))
+      }
+      BuiltinTransform::StripSourceMaps if extension == "map" => {
+        Some(TransformOutput::RemoveIf(Box::new(is_source_map)))
+      }
+      BuiltinTransform::StripSourceMaps if SCRIPT_EXTENSIONS.contains(&extension) => {
+        Some(buffered(|content| Ok(strip_source_mapping_url(&content))))
+      }
+      BuiltinTransform::StripPackageJson if name == "package.json" => {
+        Some(buffered(|content| Ok(strip_package_json(content))))
+      }
+      _ => None,
+    }
+  }
+}
+
+/// Rewrites the whole content of a file at once.
+fn buffered<F>(rewrite: F) -> TransformOutput
+where
+  F: FnOnce(Vec<u8>) -> std::io::Result<Vec<u8>> + 'static,
+{
+  TransformOutput::adapter(move |mut content| {
+    deferred(move || {
+      let mut buffer = vec![];
+      content.read_to_end(&mut buffer)?;
+      Ok(Box::new(Cursor::new(rewrite(buffer)?)))
+    })
+  })
+}
+
+fn minify_json(content: Vec<u8>) -> Vec<u8> {
+  // only valid JSON can be stripped without looking at its values
+  if serde_json::from_slice::<serde::de::IgnoredAny>(&content).is_err() {
+    return content;
+  }
+  let mut minified = Vec::with_capacity(content.len());
+  let mut in_string = false;
+  let mut escaped = false;
+  for byte in content {
+    if in_string {
+      if escaped {
+        escaped = false;
+      } else if byte == b'\\' {
+        escaped = true;
+      } else if byte == b'"' {
+        in_string = false;
+      }
+    } else if matches!(byte, b' ' | b'\t' | b'\n' | b'\r') {
+      continue;
+    } else if byte == b'"' {
+      in_string = true;
+    }
+    minified.push(byte);
+  }
+  minified
+}
+
+fn crlf_to_lf(content: &[u8]) -> Vec<u8> {
+  let mut normalized = Vec::with_capacity(content.len());
+  let mut bytes = content.iter().peekable();
+  while let Some(&byte) = bytes.next() {
+    if byte == b'\r' && bytes.peek() == Some(&&b'\n') {
+      continue;
+    }
+    normalized.push(byte);
+  }
+  normalized
+}
+
+/// Removes the `sourceMappingURL` comment on the last line with content.
+/// Comments elsewhere may be part of a string or of code that generates
+/// source maps, so they are kept.
+fn strip_source_mapping_url(content: &[u8]) -> Vec<u8> {
+  let trimmed = content.trim_ascii_end();
+  let start = trimmed
+    .iter()
+    .rposition(|byte| *byte == b'\n')
+    .map_or(0, |newline| newline + 1);
+  let line = trimmed[start..].trim_ascii_start();
+  if line.starts_with(b"//# sourceMappingURL=")
+    || line.starts_with(b"//@ sourceMappingURL=")
+    || line.starts_with(b"/*# sourceMappingURL=")
+  {
+    content[..start].to_vec()
+  } else {
+    content.to_vec()
+  }
+}
+
+/// Tells source maps apart from other files that happen to end in `.map`.
+fn is_source_map(content: &[u8]) -> bool {
+  use serde_json::Value;
+  let Ok(Value::Object(map)) = serde_json::from_slice(content) else {
+    return false;
+  };
+  map.get("version").is_some_and(Value::is_u64)
+    && (map.get("mappings").is_some_and(Value::is_string)
+      || map.get("sections").is_some_and(Value::is_array))
+}
+
+fn strip_package_json(content: Vec<u8>) -> Vec<u8> {
+  if serde_json::from_slice::<serde_json::Map<String, serde_json::Value>>(&content).is_err() {
+    return content;
+  }
+  let members = object_members(&content);
+  let is_dev = |member: &ObjectMember| PACKAGE_JSON_DEV_FIELDS.contains(&member.key.as_str());
+  let (Some(first), Some(last)) = (members.first(), members.last()) else {
+    return content;
+  };
+  if !members.iter().any(is_dev) {
+    return content;
+  }
+
+  // the kept members are joined with the separator that followed them, so
+  // the layout of the file stays the same
+  let mut stripped = content[..first.start].to_vec();
+  let mut previous: Option<usize> = None;
+  for (index, member) in members.iter().enumerate() {
+    if is_dev(member) {
+      continue;
+    }
+    if let Some(previous) = previous {
+      stripped.extend_from_slice(&content[members[previous].end..members[previous + 1].start]);
+    }
+    stripped.extend_from_slice(&content[member.start..member.end]);
+    previous = Some(index);
+  }
+  if previous.is_none() {
+    stripped = stripped.trim_ascii_end().to_vec();
+  }
+  stripped.extend_from_slice(&content[last.end..]);
+  stripped
+}
+
+/// A member of a JSON object, spanning from its key to the end of its value.
+struct ObjectMember {
+  key: String,
+  start: usize,
+  end: usize,
+}
+
+/// Lists the members of the object `content` consists of, which has to be
+/// valid JSON.
+fn object_members(content: &[u8]) -> Vec<ObjectMember> {
+  let mut members = vec![];
+  let mut depth = 0;
+  let mut in_string = false;
+  let mut escaped = false;
+  let mut string_start = 0;
+  let mut member: Option<(String, usize)> = None;
+  let mut end = 0;
+  for (index, &byte) in content.iter().enumerate() {
+    if in_string {
+      if escaped {
+        escaped = false;
+      } else if byte == b'\\' {
+        escaped = true;
+      } else if byte == b'"' {
+        in_string = false;
+        end = index + 1;
+        if depth == 1 && member.is_none() {
+          let key = serde_json::from_slice(&content[string_start..end]).unwrap_or_default();
+          member = Some((key, string_start));
+        }
+      }
+      continue;
+    }
+    match byte {
+      b' ' | b'\t' | b'\n' | b'\r' => continue,
+      b'"' => {
+        in_string = true;
+        string_start = index;
+        continue;
+      }
+      b',' | b'}' if depth == 1 => {
+        if let Some((key, start)) = member.take() {
+          members.push(ObjectMember { key, start, end });
+        }
+        if byte == b'}' {
+          depth = 0;
+        }
+        continue;
+      }
+      b'{' | b'[' => depth += 1,
+      b'}' | b']' => depth -= 1,
+      _ => {}
+    }
+    end = index + 1;
+  }
+  members
+}
@@ -1,12 +1,14 @@
 use std::ffi::OsStr;
 use std::fs;
+use std::io::{Read, Seek, SeekFrom};
 
 mod util;
 
-use util::{comp_dir, comp_file, resolve};
+use util::{comp_dir, comp_file, resolve, write_raw_archive};
 
 use anyhow::Result;
 use asar_rs::*;
+use sha2::{Digest, Sha256};
 
 #[test]
 pub fn should_create_archive_from_directory() -> Result<()> {
@@ -16,6 +18,277 @@ pub fn should_create_archive_from_directory() -> Result<()> {
   Ok(())
 }
 
+#[test]
+pub fn should_replace_existing_archive_and_unpacked_dir() -> Result<()> {
+  let _ = fs::remove_dir_all(resolve("tmp/replace"));
+  let out = resolve("tmp/replace/packthis-api.asar");
+  let unpacked = resolve("tmp/replace/packthis-api.asar.unpacked");
+  fs::create_dir_all(unpacked.join("stale"))?;
+  fs::write(unpacked.join("stale/file.txt"), "stale")?;
+  fs::write(&out, vec![0xffu8; 64 * 1024])?;
+  // not ours to remove
+  fs::create_dir_all(resolve("tmp/replace/packthis-api.asar.unpacked.old"))?;
+  fs::write(
+    resolve("tmp/replace/packthis-api.asar.unpacked.old/keep.txt"),
+    "keep",
+  )?;
+
+  let mut options = CreateOptions::new();
+  options.unpack = Some("*.png".to_owned());
+  create_package_with_options(resolve("tests/input/packthis"), &out, &options)?;
+  assert!(!unpacked.join("stale").exists());
+  assert!(unpacked.join("dir2/file2.png").is_file());
+
+  create_package(resolve("tests/input/packthis"), &out)?;
+  assert!(comp_file(&out, resolve("tests/expected/packthis.asar"))?);
+  assert!(!unpacked.exists());
+  let leftovers = fs::read_dir(resolve("tmp/replace"))?
+    .filter_map(|entry| entry.ok())
+    .filter(|entry| entry.file_name().to_string_lossy().starts_with(".asar-"))
+    .count();
+  assert_eq!(leftovers, 0);
+  assert!(resolve("tmp/replace/packthis-api.asar.unpacked.old/keep.txt").is_file());
+
+  // a new archive gets the mode of any new file
+  #[cfg(not(target_os = "windows"))]
+  {
+    use std::os::unix::fs::PermissionsExt;
+    let created = resolve("tmp/replace/created.asar");
+    create_package(resolve("tests/input/packthis"), &created)?;
+    let probe = resolve("tmp/replace/probe");
+    fs::File::create(&probe)?;
+    assert_eq!(
+      fs::metadata(&created)?.permissions().mode(),
+      fs::metadata(&probe)?.permissions().mode()
+    );
+  }
+
+  // the old unpacked files are put back if the archive can not be replaced
+  let blocked = resolve("tmp/replace/blocked.asar");
+  let blocked_unpacked = resolve("tmp/replace/blocked.asar.unpacked");
+  fs::create_dir_all(blocked.join("dir"))?;
+  fs::create_dir_all(&blocked_unpacked)?;
+  fs::write(blocked_unpacked.join("old.txt"), "old")?;
+  assert!(
+    create_package_with_options(resolve("tests/input/packthis"), &blocked, &options).is_err()
+  );
+  assert_eq!(fs::read(blocked_unpacked.join("old.txt"))?, b"old");
+  assert!(!blocked_unpacked.join("dir2").exists());
+  Ok(())
+}
+
+#[test]
+pub fn should_compute_header_integrity() -> Result<()> {
+  let out = resolve("tmp/packthis-api-header-integrity.asar");
+  let created =
+    create_package_with_options(resolve("tests/input/packthis"), &out, &CreateOptions::new())?;
+  let (header, _, _) = get_raw_header(&out)?;
+  let expected = hex::encode(Sha256::digest(header.as_bytes()));
+  assert_eq!(created.hash, expected);
+  assert_eq!(get_header_integrity(&out)?.hash, expected);
+
+  // Info.plist looks archives up by their path relative to `Contents`
+  let output = std::process::Command::new(env!("CARGO_BIN_EXE_asar"))
+    .args(["integrity", "--plist"])
+    .arg(&out)
+    .output()?;
+  assert!(output.status.success());
+  let plist = String::from_utf8(output.stdout)?;
+  assert!(plist.contains("<key>Resources/packthis-api-header-integrity.asar</key>"));
+  assert!(plist.contains(&format!("<string>{}</string>", expected)));
+  assert!(!plist.contains("tmp/"));
+  Ok(())
+}
+
+#[test]
+pub fn should_verify_integrity_of_archive() -> Result<()> {
+  let out = resolve("tmp/packthis-api-verify.asar");
+  let mut options = CreateOptions::new();
+  options.unpack = Some("*.png".to_owned());
+  create_package_with_options(resolve("tests/input/packthis"), &out, &options)?;
+  assert!(verify_package(&out)?.is_empty());
+
+  // corrupt the first byte of a packed file
+  let (_, _, header_size) = get_raw_header(&out)?;
+  let offset = match stat_file(&out, "dir1/file1.txt", None)? {
+    node::Node::File(node) => node.offset.unwrap().parse::<usize>()?,
+    _ => unreachable!(),
+  };
+  let mut data = fs::read(&out)?;
+  data[8 + header_size + offset] ^= 0xff;
+  fs::write(&out, data)?;
+
+  // corrupt an unpacked file
+  fs::write(
+    resolve("tmp/packthis-api-verify.asar.unpacked/dir2/file2.png"),
+    b"png",
+  )?;
+
+  let mismatches = verify_package(&out)?;
+  let mut paths: Vec<String> = mismatches
+    .iter()
+    .map(|m| m.path.replace('\\', "/"))
+    .collect();
+  paths.sort();
+  assert_eq!(paths, vec!["dir1/file1.txt", "dir2/file2.png"]);
+  assert!(mismatches.iter().all(|m| m.error.is_none()));
+
+  // a missing unpacked file is reported without stopping the others
+  fs::remove_file(resolve(
+    "tmp/packthis-api-verify.asar.unpacked/dir2/file2.png",
+  ))?;
+  let mismatches = verify_package(&out)?;
+  assert_eq!(mismatches.len(), 2);
+  let missing = mismatches
+    .iter()
+    .find(|m| m.path.replace('\\', "/") == "dir2/file2.png")
+    .unwrap();
+  assert!(missing.error.is_some());
+  assert!(missing.actual.is_empty());
+
+  let mut asar = AsarFile::open(&out)?;
+  assert!(asar.read_file("dir1/file1.txt").is_ok());
+  asar.set_verify_integrity(true);
+  let err = asar.read_file("dir1/file1.txt").unwrap_err();
+  assert!(matches!(
+    err.status(),
+    error::ErrorStatus::IntegrityMismatch
+  ));
+  assert!(asar.read_file("file0.txt").is_ok());
+  Ok(())
+}
+
+#[test]
+pub fn should_verify_only_touched_blocks_of_a_range() -> Result<()> {
+  const BLOCK_SIZE: usize = 4 * 1024 * 1024;
+  let input = resolve("tmp/range-input");
+  let out = resolve("tmp/range-api.asar");
+  let expected: Vec<u8> = (0..BLOCK_SIZE * 2 + 1024)
+    .map(|i| (i % 251) as u8)
+    .collect();
+  fs::create_dir_all(&input)?;
+  fs::write(input.join("large.bin"), &expected)?;
+  create_package(&input, &out)?;
+
+  let mut asar = AsarFile::open(&out)?;
+  asar.set_verify_integrity(true);
+  let actual = asar.read_range("large.bin", BLOCK_SIZE as u64 - 10, 20)?;
+  assert_eq!(actual, expected[BLOCK_SIZE - 10..BLOCK_SIZE + 10]);
+  let err = asar
+    .read_range("large.bin", expected.len() as u64 - 10, 20)
+    .unwrap_err();
+  assert!(matches!(err.status(), error::ErrorStatus::OutOfRange));
+
+  // corrupt the last block only
+  let (_, _, header_size) = get_raw_header(&out)?;
+  let mut data = fs::read(&out)?;
+  data[8 + header_size + BLOCK_SIZE * 2 + 5] ^= 0xff;
+  fs::write(&out, data)?;
+
+  let mut asar = AsarFile::open(&out)?;
+  asar.set_verify_integrity(true);
+  assert_eq!(asar.read_range("large.bin", 0, 100)?, expected[0..100]);
+  let err = asar
+    .read_range("large.bin", BLOCK_SIZE as u64 * 2, 10)
+    .unwrap_err();
+  assert!(matches!(
+    err.status(),
+    error::ErrorStatus::IntegrityMismatch
+  ));
+  Ok(())
+}
+
+#[test]
+pub fn should_not_verify_files_without_integrity() -> Result<()> {
+  // tampered content whose hashes were simply left out of the header
+  let out = resolve("tmp/no-integrity/tampered.asar");
+  write_raw_archive(
+    &out,
+    r#"{"files":{"main.js":{"size":8,"offset":"0"}}}"#,
+    b"tampered",
+  )?;
+  let mismatches = verify_package(&out)?;
+  assert_eq!(mismatches.len(), 1);
+  assert_eq!(mismatches[0].path, "main.js");
+  assert!(mismatches[0].expected.is_empty());
+  assert!(mismatches[0].error.is_some());
+
+  let mut asar = AsarFile::open(&out)?;
+  assert_eq!(asar.read_file("main.js")?, b"tampered");
+  asar.set_verify_integrity(true);
+  let err = asar.read_file("main.js").unwrap_err();
+  assert!(matches!(
+    err.status(),
+    error::ErrorStatus::IntegrityMismatch
+  ));
+  let err = asar.read_range("main.js", 0, 4).unwrap_err();
+  assert!(matches!(
+    err.status(),
+    error::ErrorStatus::IntegrityMismatch
+  ));
+  let err = asar.read_file_mapped("main.js").unwrap_err();
+  assert!(matches!(
+    err.status(),
+    error::ErrorStatus::IntegrityMismatch
+  ));
+  let dest = resolve("tmp/no-integrity/main.js");
+  let err = asar.extract_file("main.js", &dest).unwrap_err();
+  assert!(matches!(
+    err.status(),
+    error::ErrorStatus::IntegrityMismatch
+  ));
+  assert!(!dest.exists());
+
+  // entries that can not be looked up by name are reported too
+  let integrity = format!(
+    r#"{{"algorithm":"SHA256","hash":"{0}","blockSize":4194304,"blocks":["{0}"]}}"#,
+    hex::encode(Sha256::digest(b"root"))
+  );
+  let out = resolve("tmp/no-integrity/dotdot.asar");
+  write_raw_archive(
+    &out,
+    &format!(
+      r#"{{"files":{{"..":{{"files":{{"passwd":{{"size":4,"offset":"0","integrity":{0}}}}}}},"ok":{{"size":4,"offset":"0","integrity":{0}}}}}}}"#,
+      integrity
+    ),
+    b"root",
+  )?;
+  let mismatches = verify_package(&out)?;
+  assert_eq!(mismatches.len(), 1);
+  assert_eq!(mismatches[0].path, "../passwd");
+  assert!(mismatches[0].error.is_some());
+  Ok(())
+}
+
+#[test]
+pub fn should_extract_unpacked_files_in_verified_mode() -> Result<()> {
+  let input = resolve("tmp/verified-unpacked/input");
+  let out = resolve("tmp/verified-unpacked/app.asar");
+  let _ = fs::remove_dir_all(resolve("tmp/verified-unpacked"));
+  fs::create_dir_all(input.join("bin"))?;
+  fs::write(input.join("bin/run.sh"), "#!/bin/sh\n")?;
+  #[cfg(not(target_os = "windows"))]
+  {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(input.join("bin/run.sh"), fs::Permissions::from_mode(0o750))?;
+  }
+  let mut options = CreateOptions::new();
+  options.unpack = Some("*.sh".to_owned());
+  create_package_with_options(&input, &out, &options)?;
+
+  let mut asar = AsarFile::open(&out)?;
+  asar.set_verify_integrity(true);
+  let dest = resolve("tmp/verified-unpacked/new/dir/run.sh");
+  asar.extract_file("bin/run.sh", &dest)?;
+  assert_eq!(fs::read(&dest)?, b"#!/bin/sh\n");
+  #[cfg(not(target_os = "windows"))]
+  {
+    use std::os::unix::fs::PermissionsExt;
+    assert_eq!(fs::metadata(&dest)?.permissions().mode() & 0o777, 0o750);
+  }
+  Ok(())
+}
+
 #[test]
 pub fn should_create_archive_from_directory_with_link() -> Result<()> {
   let out = resolve("tmp/packthis-api-link.asar");
@@ -47,53 +320,55 @@ pub fn should_create_archive_from_directory_without_hidden_files() -> Result<()>
   Ok(())
 }
 
-#[test]
-pub fn should_create_archive_from_directory_with_transformed_files() -> Result<()> {
-  let out = resolve("tmp/packthis-api-transformed.asar");
-  let mut options = CreateOptions::new();
+struct Reverser {
+  flushed: bool,
+  data: String,
+}
 
-  struct Reverser {
-    flushed: bool,
-    data: String,
+impl std::io::Read for Reverser {
+  fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+    if self.flushed {
+      let ret = self.data.as_bytes();
+      let len = ret.len();
+      buf[0..len].copy_from_slice(&ret[0..]);
+      Ok(len)
+    } else {
+      Ok(0)
+    }
   }
+}
 
-  impl std::io::Read for Reverser {
-    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-      if self.flushed {
-        let ret = self.data.as_bytes();
-        let len = ret.len();
-        buf[0..len].copy_from_slice(&ret[0..]);
-        Ok(len)
-      } else {
-        Ok(0)
-      }
-    }
+impl std::io::Write for Reverser {
+  fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+    self.data += core::str::from_utf8(buf).unwrap();
+    Ok(buf.len())
   }
 
-  impl std::io::Write for Reverser {
-    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-      self.data += &String::from_utf8(buf.to_vec()).unwrap();
-      Ok(buf.len())
-    }
+  fn flush(&mut self) -> std::io::Result<()> {
+    self.data = self.data.chars().rev().collect();
+    self.flushed = true;
+    Ok(())
+  }
+}
 
-    fn flush(&mut self) -> std::io::Result<()> {
-      self.data = self.data.chars().rev().collect();
-      self.flushed = true;
-      Ok(())
-    }
+impl Transform for Reverser {}
+
+fn reverse_file0(filename: &str) -> Option<Box<dyn Transform>> {
+  if std::path::Path::new(filename).file_name().unwrap() == OsStr::new("file0.txt") {
+    return Some(Box::new(Reverser {
+      flushed: false,
+      data: "".to_owned(),
+    }));
   }
+  None
+}
 
-  impl Transform for Reverser {}
+#[test]
+pub fn should_create_archive_from_directory_with_transformed_files() -> Result<()> {
+  let out = resolve("tmp/packthis-api-transformed.asar");
+  let mut options = CreateOptions::new();
 
-  options.transform = Some(|filename| -> Option<Box<dyn Transform>> {
-    if std::path::Path::new(filename).file_name().unwrap() == OsStr::new("file0.txt") {
-      return Some(Box::new(Reverser {
-        flushed: false,
-        data: "".to_owned(),
-      }));
-    }
-    None
-  });
+  options.transform = Some(reverse_file0);
   create_package_with_options(resolve("tests/input/packthis"), &out, &options)?;
   assert!(comp_file(
     &out,
@@ -102,6 +377,38 @@ pub fn should_create_archive_from_directory_with_transformed_files() -> Result<(
   Ok(())
 }
 
+#[test]
+pub fn should_hash_the_transformed_content() -> Result<()> {
+  let out = resolve("tmp/transformed-integrity/packthis.asar");
+  let mut options = CreateOptions::new();
+  options.transform = Some(reverse_file0);
+  create_package_with_options(resolve("tests/input/packthis"), &out, &options)?;
+
+  let asar = AsarFile::open(&out)?;
+  assert_eq!(asar.read_file("file0.txt")?, b"tnetnoc 0elif");
+  let mut checked = 0;
+  for path in asar.list(&ListOptions::new())? {
+    let path = path.trim_start_matches('/');
+    if let node::Node::File(node::FileNode {
+      integrity: Some(integrity),
+      ..
+    }) = asar.stat_file(path, None)?
+    {
+      let content = asar.read_file(path)?;
+      assert_eq!(
+        integrity.hash,
+        hex::encode(Sha256::digest(&content)),
+        "{}",
+        path
+      );
+      checked += 1;
+    }
+  }
+  assert!(checked > 0);
+  assert!(asar.verify()?.is_empty());
+  Ok(())
+}
+
 #[test]
 pub fn should_create_archive_from_directory_with_nothing_packed() -> Result<()> {
   let out = resolve("tmp/packthis-api-unpacked.asar");
@@ -152,6 +459,37 @@ pub fn list_files_in_archive_with_option() -> Result<()> {
   Ok(())
 }
 
+#[test]
+pub fn should_reject_truncated_or_malformed_headers() -> Result<()> {
+  let data = fs::read(resolve("tests/expected/packthis.asar"))?;
+  let out = resolve("tmp/truncated-header.asar");
+  fs::create_dir_all(resolve("tmp"))?;
+
+  for len in [0, 4, 7, 8, 12, 16, 100] {
+    fs::write(&out, &data[0..len])?;
+    let err = list_package(&out).unwrap_err();
+    assert!(matches!(
+      err.status(),
+      error::ErrorStatus::InvalidHeaderSize | error::ErrorStatus::InvalidHeader
+    ));
+  }
+
+  // string length pointing past the end of the header pickle
+  let mut bad_length = data.clone();
+  bad_length[12..16].copy_from_slice(&u32::MAX.to_le_bytes());
+  fs::write(&out, &bad_length)?;
+  let err = list_package(&out).unwrap_err();
+  assert!(matches!(err.status(), error::ErrorStatus::InvalidHeader));
+
+  // invalid UTF-8 in the header string
+  let mut bad_utf8 = data.clone();
+  bad_utf8[16] = 0xff;
+  fs::write(&out, &bad_utf8)?;
+  let err = list_package(&out).unwrap_err();
+  assert!(matches!(err.status(), error::ErrorStatus::InvalidHeader));
+  Ok(())
+}
+
 #[test]
 pub fn should_extract_a_text_file_from_archive() -> Result<()> {
   let actual = String::from_utf8(extract_file(
@@ -182,6 +520,41 @@ pub fn should_extract_a_binary_file_from_archive_with_unpacked_files() -> Result
   Ok(())
 }
 
+#[test]
+pub fn should_stream_a_file_from_archive() -> Result<()> {
+  let expected = fs::read(resolve("tests/expected/extractthis/dir2/file2.png"))?;
+  let asar = AsarFile::open(resolve("tests/input/extractthis.asar"))?;
+  let mut entry = asar.open_entry("dir2/file2.png")?;
+  assert_eq!(entry.size(), expected.len() as u64);
+
+  let mut head = [0u8; 8];
+  entry.read_exact(&mut head)?;
+  assert_eq!(head, expected[0..8]);
+
+  entry.seek(SeekFrom::End(-4))?;
+  let mut tail = vec![];
+  entry.read_to_end(&mut tail)?;
+  assert_eq!(tail, expected[expected.len() - 4..]);
+
+  entry.seek(SeekFrom::Start(0))?;
+  let mut actual = vec![];
+  entry.read_to_end(&mut actual)?;
+  assert_eq!(actual, expected);
+  Ok(())
+}
+
+#[test]
+pub fn should_stream_an_unpacked_file_from_archive() -> Result<()> {
+  let expected = fs::read(resolve("tests/expected/extractthis/dir2/file2.png"))?;
+  let asar = AsarFile::open(resolve("tests/input/extractthis-unpack.asar"))?;
+  let mut entry = asar.open_entry("dir2/file2.png")?;
+  entry.seek(SeekFrom::Start(4))?;
+  let mut actual = vec![];
+  entry.read_to_end(&mut actual)?;
+  assert_eq!(actual, expected[4..]);
+  Ok(())
+}
+
 #[test]
 pub fn should_extract_an_archive() -> Result<()> {
   let out = resolve("tmp/extractthis-api");
@@ -253,3 +626,866 @@ pub fn should_extract_files_or_directories_whose_names_are_properties_of_object_
   comp_dir("tests/input/packthis-object-prototype/", "tmp/packthis-object-prototype")?;
   Ok(())
 }
+
+#[test]
+pub fn should_refuse_to_extract_entries_outside_of_destination() -> Result<()> {
+  let headers = [
+    (
+      "dotdot",
+      r#"{"files":{"..":{"files":{"evil.txt":{"size":4,"offset":"0"}}}}}"#,
+    ),
+    (
+      "separator",
+      r#"{"files":{"a/../../evil.txt":{"size":4,"offset":"0"}}}"#,
+    ),
+    (
+      "backslash",
+      r#"{"files":{"..\\evil.txt":{"size":4,"offset":"0"}}}"#,
+    ),
+    (
+      "absolute",
+      r#"{"files":{"/tmp/evil.txt":{"size":4,"offset":"0"}}}"#,
+    ),
+    (
+      "empty",
+      r#"{"files":{"":{"files":{"evil.txt":{"size":4,"offset":"0"}}}}}"#,
+    ),
+    ("link-up", r#"{"files":{"evil":{"link":"../../evil.txt"}}}"#),
+    (
+      "link-nested-up",
+      r#"{"files":{"dir":{"files":{}},"evil":{"link":"dir/../../evil.txt"}}}"#,
+    ),
+    (
+      "link-absolute",
+      r#"{"files":{"evil":{"link":"/etc/passwd"}}}"#,
+    ),
+  ];
+  for (name, header) in headers {
+    let archive = resolve(format!("tmp/traversal/{}.asar", name));
+    let dest = resolve(format!("tmp/traversal/{}/out", name));
+    write_raw_archive(&archive, header, b"evil")?;
+    let err = extract_all(&archive, &dest).unwrap_err();
+    assert!(
+      matches!(err.status(), error::ErrorStatus::PathTraversal),
+      "{} was not rejected",
+      name
+    );
+    assert!(!resolve(format!("tmp/traversal/{}/evil.txt", name)).exists());
+    assert!(!dest.exists());
+  }
+
+  // links that stay inside the archive are still extracted
+  let archive = resolve("tmp/traversal/inside.asar");
+  let dest = resolve("tmp/traversal/inside/out");
+  write_raw_archive(
+    &archive,
+    r#"{"files":{"dir":{"files":{"file.txt":{"size":4,"offset":"0"}}},"link":{"link":"dir/./../dir/file.txt"}}}"#,
+    b"good",
+  )?;
+  extract_all(&archive, &dest)?;
+  assert_eq!(fs::read(dest.join("dir/file.txt"))?, b"good");
+  Ok(())
+}
+
+#[test]
+pub fn should_detect_link_cycles() -> Result<()> {
+  let archive = resolve("tmp/link-cycle.asar");
+  let mut files: Vec<String> = vec![
+    r#""a":{"link":"b"}"#.to_owned(),
+    r#""b":{"link":"a"}"#.to_owned(),
+    r#""self":{"link":"self"}"#.to_owned(),
+    r#""file.txt":{"size":4,"offset":"0"}"#.to_owned(),
+  ];
+  for i in 0..100 {
+    files.push(format!(r#""chain{}":{{"link":"chain{}"}}"#, i, i + 1));
+  }
+  files.push(r#""chain100":{"link":"file.txt"}"#.to_owned());
+  write_raw_archive(
+    &archive,
+    &format!(r#"{{"files":{{{}}}}}"#, files.join(",")),
+    b"data",
+  )?;
+
+  let asar = AsarFile::open(&archive)?;
+  for name in ["a", "b", "self", "chain0"] {
+    let err = asar.read_file(name).unwrap_err();
+    assert!(matches!(err.status(), error::ErrorStatus::LinkLoop));
+    let err = asar.stat_file(name, None).err().unwrap();
+    assert!(matches!(err.status(), error::ErrorStatus::LinkLoop));
+    let err = asar
+      .extract_file(name, resolve("tmp/link-cycle-out"))
+      .unwrap_err();
+    assert!(matches!(err.status(), error::ErrorStatus::LinkLoop));
+  }
+  assert!(asar.stat_file("a", Some(false))?.is_link());
+  assert_eq!(asar.read_file("chain80")?, b"data");
+  Ok(())
+}
+
+#[test]
+pub fn should_resolve_links_in_intermediate_path_components() -> Result<()> {
+  let archive = resolve("tmp/linked-dirs.asar");
+  write_raw_archive(
+    &archive,
+    r#"{"files":{
+      "store":{"files":{"pkg":{"files":{
+        "index.js":{"size":4,"offset":"0"},
+        "native.node":{"size":6,"unpacked":true}
+      }}}},
+      "node_modules":{"files":{"pkg":{"link":"store/pkg"}}},
+      "nm":{"link":"node_modules"}
+    }}"#,
+    b"main",
+  )?;
+  fs::create_dir_all(resolve("tmp/linked-dirs.asar.unpacked/store/pkg"))?;
+  fs::write(
+    resolve("tmp/linked-dirs.asar.unpacked/store/pkg/native.node"),
+    b"native",
+  )?;
+
+  let asar = AsarFile::open(&archive)?;
+  assert_eq!(asar.read_file("node_modules/pkg/index.js")?, b"main");
+  assert_eq!(asar.read_file("nm/pkg/index.js")?, b"main");
+  assert_eq!(asar.read_file("nm/pkg/./../pkg/native.node")?, b"native");
+  assert_eq!(asar.realpath("nm/pkg/index.js")?, "store/pkg/index.js");
+  assert_eq!(asar.realpath("node_modules/pkg")?, "store/pkg");
+  assert!(asar.stat_file("nm/pkg", Some(false))?.is_link());
+  assert!(asar.stat_file("nm/pkg", None)?.is_dir());
+
+  let err = asar.realpath("nm/pkg/index.js/..").unwrap_err();
+  assert!(matches!(err.status(), error::ErrorStatus::ExpectDirNode));
+  let err = asar.realpath("nm/missing").unwrap_err();
+  assert!(matches!(err.status(), error::ErrorStatus::NoSuchEntry));
+  Ok(())
+}
+
+#[test]
+pub fn should_reject_entries_out_of_archive_range() -> Result<()> {
+  let archive = resolve("tmp/out-of-range.asar");
+  write_raw_archive(
+    &archive,
+    r#"{"files":{
+      "ok.txt":{"size":4,"offset":"0"},
+      "past-end.txt":{"size":4,"offset":"2"},
+      "far.txt":{"size":4,"offset":"1000"},
+      "overflow.txt":{"size":4,"offset":"18446744073709551615"}
+    }}"#,
+    b"data",
+  )?;
+  let asar = AsarFile::open(&archive)?;
+  assert_eq!(asar.read_file("ok.txt")?, b"data");
+  for name in ["past-end.txt", "far.txt", "overflow.txt"] {
+    let err = asar.read_file(name).unwrap_err();
+    assert!(matches!(err.status(), error::ErrorStatus::OutOfRange));
+    let err = asar.open_entry(name).err().unwrap();
+    assert!(matches!(err.status(), error::ErrorStatus::OutOfRange));
+  }
+  let err = extract_all(&archive, resolve("tmp/out-of-range")).unwrap_err();
+  assert!(matches!(err.status(), error::ErrorStatus::Extraction));
+  assert_eq!(fs::read(resolve("tmp/out-of-range/ok.txt"))?, b"data");
+
+  // a truncated archive
+  let data = fs::read(resolve("tests/input/extractthis.asar"))?;
+  let truncated = resolve("tmp/truncated-data.asar");
+  fs::write(&truncated, &data[0..data.len() - 1])?;
+  let asar = AsarFile::open(&truncated)?;
+  assert!(asar.read_file("dir2/file3.txt").is_ok());
+  let err = asar.read_file("file0.txt").unwrap_err();
+  assert!(matches!(err.status(), error::ErrorStatus::OutOfRange));
+  Ok(())
+}
+
+#[test]
+pub fn should_report_structural_problems() -> Result<()> {
+  let out = resolve("tmp/packthis-api-check.asar");
+  let mut options = CreateOptions::new();
+  options.unpack = Some("*.png".to_owned());
+  create_package_with_options(resolve("tests/input/packthis"), &out, &options)?;
+  assert_eq!(check_package(&out)?, vec![]);
+
+  let archive = resolve("tmp/check.asar");
+  write_raw_archive(
+    &archive,
+    r#"{"files":{
+      "a.txt":{"size":4,"offset":"0"},
+      "b.txt":{"size":4,"offset":"2"},
+      "c.txt":{"size":4,"offset":"10"},
+      "d.txt":{"size":4,"offset":"30"},
+      "e.txt":{"size":4,"offset":"four"},
+      "f.txt":{"size":4},
+      "g.txt":{"size":4,"unpacked":true},
+      "..":{"files":{}},
+      "broken":{"link":"missing.txt"},
+      "outside":{"link":"../a.txt"}
+    }}"#,
+    b"0123456789abcdefghij",
+  )?;
+  let findings = check_package(&archive)?;
+  let expected = vec![
+    Finding::IllegalName {
+      path: "..".to_owned(),
+    },
+    Finding::MissingIntegrity {
+      path: "a.txt".to_owned(),
+    },
+    Finding::MissingIntegrity {
+      path: "b.txt".to_owned(),
+    },
+    Finding::MissingLinkTarget {
+      path: "broken".to_owned(),
+      link: "missing.txt".to_owned(),
+    },
+    Finding::MissingIntegrity {
+      path: "c.txt".to_owned(),
+    },
+    Finding::MissingIntegrity {
+      path: "d.txt".to_owned(),
+    },
+    Finding::MissingIntegrity {
+      path: "e.txt".to_owned(),
+    },
+    Finding::InvalidOffset {
+      path: "e.txt".to_owned(),
+      offset: Some("four".to_owned()),
+    },
+    Finding::MissingIntegrity {
+      path: "f.txt".to_owned(),
+    },
+    Finding::InvalidOffset {
+      path: "f.txt".to_owned(),
+      offset: None,
+    },
+    Finding::MissingIntegrity {
+      path: "g.txt".to_owned(),
+    },
+    Finding::MissingUnpacked {
+      path: "g.txt".to_owned(),
+    },
+    Finding::IllegalLink {
+      path: "outside".to_owned(),
+      link: "../a.txt".to_owned(),
+    },
+    Finding::Overlap {
+      path: "b.txt".to_owned(),
+      other: "a.txt".to_owned(),
+      offset: 2,
+      size: 4,
+    },
+    Finding::Gap { offset: 6, size: 4 },
+    Finding::OutOfBounds {
+      path: "d.txt".to_owned(),
+      offset: 30,
+      size: 4,
+    },
+    Finding::Gap {
+      offset: 14,
+      size: 6,
+    },
+  ];
+  assert_eq!(findings, expected);
+  Ok(())
+}
+
+#[test]
+pub fn should_build_archive_from_memory() -> Result<()> {
+  // the parent directory of the archive does not exist yet
+  let _ = fs::remove_dir_all(resolve("tmp/builder"));
+  let out = resolve("tmp/builder/built.asar");
+
+  let mut builder = AsarBuilder::new();
+  builder.add_file("package.json", r#"{"name":"app"}"#, false)?;
+  builder.add_file(
+    "bin/run.sh",
+    FileSource::reader(std::io::Cursor::new(b"#!/bin/sh\n".to_vec())),
+    true,
+  )?;
+  builder.add_file("native/addon.node", vec![0u8, 1, 2, 3], false)?;
+  builder.add_file("native/helper", "#!/bin/sh\n", true)?;
+  builder.add_dir("empty")?;
+  builder.add_symlink("main.json", "package.json")?;
+  builder.mark_unpacked("native")?;
+  assert!(builder.add_file("../escape.txt", "x", false).is_err());
+  assert!(builder.add_symlink("outside", "../package.json").is_err());
+  builder.write(&out)?;
+
+  let mut asar = AsarFile::open(&out)?;
+  asar.set_verify_integrity(true);
+  assert_eq!(asar.read_file("package.json")?, br#"{"name":"app"}"#);
+  assert_eq!(asar.read_file("main.json")?, br#"{"name":"app"}"#);
+  assert_eq!(asar.read_file("bin/run.sh")?, b"#!/bin/sh\n");
+  assert_eq!(asar.read_file("native/addon.node")?, vec![0u8, 1, 2, 3]);
+  assert_eq!(
+    fs::read(resolve("tmp/builder/built.asar.unpacked/native/addon.node"))?,
+    vec![0u8, 1, 2, 3]
+  );
+  assert!(asar.stat_file("empty", None)?.is_dir());
+  assert!(asar.verify()?.is_empty());
+  assert_eq!(asar.check(), vec![]);
+
+  // an unpacked executable is told by its mode, not by the header
+  match asar.stat_file("native/helper", None)? {
+    node::Node::File(node) => assert_eq!(node.executable, None),
+    _ => unreachable!(),
+  }
+  #[cfg(not(target_os = "windows"))]
+  {
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+    let probe = resolve("tmp/builder/probe");
+    fs::OpenOptions::new()
+      .create(true)
+      .truncate(true)
+      .write(true)
+      .mode(0o777)
+      .open(&probe)?;
+    assert_eq!(
+      fs::metadata(resolve("tmp/builder/built.asar.unpacked/native/helper"))?
+        .permissions()
+        .mode(),
+      fs::metadata(&probe)?.permissions().mode()
+    );
+  }
+  Ok(())
+}
+
+struct MemoryUnpacked(std::collections::HashMap<String, Vec<u8>>);
+
+impl UnpackedProvider for MemoryUnpacked {
+  fn open(&self, path: &str) -> std::io::Result<(Box<dyn ReadSeek>, u64)> {
+    let bytes = self
+      .0
+      .get(path)
+      .cloned()
+      .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, path.to_owned()))?;
+    let size = bytes.len() as u64;
+    Ok((Box::new(std::io::Cursor::new(bytes)), size))
+  }
+}
+
+#[test]
+pub fn should_open_archive_from_bytes() -> Result<()> {
+  let bytes = fs::read(resolve("tests/input/extractthis.asar"))?;
+  let asar = AsarFile::from_bytes(&bytes[..])?;
+  assert_eq!(
+    asar.list(&ListOptions::new())?,
+    list_package(resolve("tests/input/extractthis.asar"))?
+  );
+  assert!(asar.stat_file("dir1", None)?.is_dir());
+  assert_eq!(
+    asar.read_file("dir1/file1.txt")?,
+    fs::read(resolve("tests/expected/extractthis/dir1/file1.txt"))?
+  );
+  let out = resolve("tmp/extractthis-from-bytes");
+  asar.extract_all(&out)?;
+  assert!(comp_dir(&out, resolve("tests/expected/extractthis"))?);
+  Ok(())
+}
+
+#[test]
+pub fn should_read_unpacked_files_through_a_provider() -> Result<()> {
+  let fd = fs::File::open(resolve("tests/input/extractthis-unpack.asar"))?;
+  let mut asar = AsarFile::from_reader(fd)?;
+  let missing_unpacked = |asar: &AsarFile| {
+    asar
+      .check()
+      .into_iter()
+      .filter(|finding| matches!(finding, Finding::MissingUnpacked { .. }))
+      .count()
+  };
+  assert!(asar.read_file("dir2/file2.png").is_err());
+  assert_eq!(missing_unpacked(&asar), 1);
+
+  let png = fs::read(resolve(
+    "tests/input/extractthis-unpack.asar.unpacked/dir2/file2.png",
+  ))?;
+  let mut files = std::collections::HashMap::new();
+  files.insert("dir2/file2.png".to_owned(), png.clone());
+  asar.set_unpacked_provider(MemoryUnpacked(files));
+  assert_eq!(asar.read_file("dir2/file2.png")?, png);
+  assert_eq!(missing_unpacked(&asar), 0);
+
+  let out = resolve("tmp/extractthis-unpack-provider");
+  asar.extract_all(&out)?;
+  assert!(comp_dir(&out, resolve("tests/expected/extractthis"))?);
+  Ok(())
+}
+
+#[test]
+pub fn should_open_archive_embedded_in_another_file() -> Result<()> {
+  let archive = fs::read(resolve("tests/input/extractthis.asar"))?;
+  let mut container = b"launcher binary".repeat(100);
+  let base_offset = container.len() as u64;
+  container.extend_from_slice(&archive);
+  container.extend_from_slice(b"trailing signature block");
+  let out = resolve("tmp/embedded/launcher.bin");
+  fs::create_dir_all(resolve("tmp/embedded"))?;
+  fs::write(&out, &container)?;
+
+  let locations = locate_package(&out)?;
+  assert_eq!(locations.len(), 1);
+  assert_eq!(locations[0].offset, base_offset);
+  assert_eq!(locations[0].len, archive.len() as u64);
+
+  let asar = AsarFile::open_at(&out, base_offset, Some(locations[0].len))?;
+  assert_eq!(
+    asar.read_file("dir2/file2.png")?,
+    fs::read(resolve("tests/expected/extractthis/dir2/file2.png"))?
+  );
+  assert!(asar
+    .check()
+    .iter()
+    .all(|finding| !matches!(finding, Finding::Gap { .. })));
+  let dest = resolve("tmp/embedded/extracted");
+  asar.extract_all(&dest)?;
+  assert!(comp_dir(&dest, resolve("tests/expected/extractthis"))?);
+
+  assert!(AsarFile::open_at(&out, 0, None).is_err());
+  Ok(())
+}
+
+#[test]
+pub fn should_borrow_packed_files_from_a_mapping() -> Result<()> {
+  let archive = resolve("tests/input/extractthis-unpack.asar");
+  // Safety: nothing modifies the test inputs
+  let asar = unsafe { AsarFile::open_mmap(&archive)? };
+  let text = asar.read_file_mapped("dir1/file1.txt")?;
+  let png = asar.read_file_mapped("dir2/file2.png")?;
+  assert!(matches!(text, std::borrow::Cow::Borrowed(..)));
+  assert!(matches!(png, std::borrow::Cow::Owned(..)));
+  assert_eq!(
+    &text[..],
+    &fs::read(resolve("tests/expected/extractthis/dir1/file1.txt"))?[..]
+  );
+  assert_eq!(
+    &png[..],
+    &fs::read(resolve("tests/expected/extractthis/dir2/file2.png"))?[..]
+  );
+
+  let asar = AsarFile::open(&archive)?;
+  let text = asar.read_file_mapped("dir1/file1.txt")?;
+  assert!(matches!(text, std::borrow::Cow::Owned(..)));
+  assert_eq!(
+    &text[..],
+    &fs::read(resolve("tests/expected/extractthis/dir1/file1.txt"))?[..]
+  );
+  Ok(())
+}
+
+#[test]
+pub fn should_read_entries_from_several_threads() -> Result<()> {
+  fn assert_send_sync<T: Send + Sync>() {}
+  assert_send_sync::<AsarFile>();
+
+  let out = resolve("tmp/threads/many.asar");
+  fs::create_dir_all(resolve("tmp/threads"))?;
+  let content = |i: usize| format!("file {} ", i).repeat(i * 37 + 1);
+  let mut builder = AsarBuilder::new();
+  for i in 0..64 {
+    builder.add_file(&format!("dir{}/file{}.txt", i % 4, i), content(i), false)?;
+  }
+  builder.write(&out)?;
+
+  let archives = [
+    AsarFile::open(&out)?,
+    // Safety: the archive is only read from here on
+    unsafe { AsarFile::open_mmap(&out)? },
+    AsarFile::from_reader(fs::File::open(&out)?)?,
+  ];
+  for asar in archives.iter() {
+    std::thread::scope(|scope| {
+      for thread in 0..8 {
+        scope.spawn(move || {
+          for n in 0..64 {
+            let i = (n + thread * 8) % 64;
+            let filename = format!("dir{}/file{}.txt", i % 4, i);
+            assert_eq!(asar.read_file(&filename).unwrap(), content(i).into_bytes());
+            let mut entry = asar.open_entry(&filename).unwrap();
+            let mut buffer = String::new();
+            entry.read_to_string(&mut buffer).unwrap();
+            assert_eq!(buffer, content(i));
+          }
+        });
+      }
+    });
+  }
+  Ok(())
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn should_read_and_extract_asynchronously() -> Result<()> {
+  use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+  let asar = AsyncAsarFile::open(resolve("tests/input/extractthis-unpack.asar")).await?;
+  assert!(asar.stat_file("dir1", None)?.is_dir());
+  let text = fs::read(resolve("tests/expected/extractthis/dir1/file1.txt"))?;
+  let png = fs::read(resolve("tests/expected/extractthis/dir2/file2.png"))?;
+  assert_eq!(asar.read_file("dir1/file1.txt").await?, text);
+
+  let mut entry = asar.open_entry("dir1/file1.txt").await?;
+  let mut buffer = vec![];
+  entry.read_to_end(&mut buffer).await?;
+  assert_eq!(buffer, text);
+  entry.seek(SeekFrom::Start(2)).await?;
+  let mut buffer = vec![];
+  entry.read_to_end(&mut buffer).await?;
+  assert_eq!(buffer, text[2..]);
+
+  let mut entry = asar.open_entry("dir2/file2.png").await?;
+  entry.seek(SeekFrom::End(-4)).await?;
+  let mut buffer = vec![];
+  entry.read_to_end(&mut buffer).await?;
+  assert_eq!(buffer, png[png.len() - 4..]);
+
+  let out = resolve("tmp/extractthis-async");
+  asar.extract_all(&out).await?;
+  assert!(comp_dir(&out, resolve("tests/expected/extractthis"))?);
+  asar
+    .extract_file("dir2/file2.png", resolve("tmp/extractthis-async/copy.png"))
+    .await?;
+  assert_eq!(fs::read(resolve("tmp/extractthis-async/copy.png"))?, png);
+
+  // archives that are not on disk stream through their own source and
+  // unpacked provider
+  let bytes = fs::read(resolve("tests/input/extractthis-unpack.asar"))?;
+  let mut memory = AsarFile::from_bytes(bytes)?;
+  memory.set_unpacked_provider(MemoryUnpacked(
+    [("dir2/file2.png".to_owned(), png.clone())].into(),
+  ));
+  let asar = AsyncAsarFile::from_asar(memory);
+  let mut buffer = vec![];
+  asar
+    .open_entry("dir1/file1.txt")
+    .await?
+    .read_to_end(&mut buffer)
+    .await?;
+  assert_eq!(buffer, text);
+  let mut entry = asar.open_entry("dir2/file2.png").await?;
+  assert_eq!(entry.size(), png.len() as u64);
+  let mut buffer = vec![];
+  entry.read_to_end(&mut buffer).await?;
+  assert_eq!(buffer, png);
+
+  let asar = AsyncAsarFile::from_asar(AsarFile::from_bytes(fs::read(resolve(
+    "tests/input/extractthis-unpack.asar",
+  ))?)?);
+  assert!(asar.open_entry("dir2/file2.png").await.is_err());
+  Ok(())
+}
+
+#[test]
+pub fn should_extract_in_parallel() -> Result<()> {
+  let out = resolve("tmp/parallel/many.asar");
+  fs::create_dir_all(resolve("tmp/parallel"))?;
+  let mut builder = AsarBuilder::new();
+  for i in 0..200 {
+    builder.add_file(
+      &format!("dir{}/sub{}/file{}.txt", i % 5, i % 3, i),
+      format!("content {}", i).repeat(i + 1),
+      false,
+    )?;
+  }
+  builder.add_dir("empty/nested")?;
+  builder.add_file("bin/tool", "#!/bin/sh\n", true)?;
+  builder.add_symlink("link.txt", "dir0/sub0/file0.txt")?;
+  builder.mark_unpacked("dir1")?;
+  builder.write(&out)?;
+
+  let sequential = resolve("tmp/parallel/sequential");
+  let parallel = resolve("tmp/parallel/parallel");
+  extract_all(&out, &sequential)?;
+  let mut options = ExtractOptions::new();
+  options.threads = 8;
+  extract_all_with_options(&out, &parallel, &options)?;
+  assert!(comp_dir(&sequential, &parallel)?);
+  assert!(parallel.join("empty/nested").is_dir());
+  assert_eq!(
+    fs::read(parallel.join("link.txt"))?,
+    fs::read(parallel.join("dir0/sub0/file0.txt"))?
+  );
+
+  let broken = resolve("tmp/parallel/broken.asar");
+  let files: Vec<String> = (0..16)
+    .map(|i| format!(r#""file{}.txt":{{"size":4,"offset":"{}"}}"#, i, i * 4))
+    .collect();
+  write_raw_archive(
+    &broken,
+    &format!(r#"{{"files":{{{}}}}}"#, files.join(",")),
+    b"data",
+  )?;
+  options.threads = 0;
+  let err =
+    extract_all_with_options(&broken, resolve("tmp/parallel/broken"), &options).unwrap_err();
+  assert!(matches!(err.status(), error::ErrorStatus::Extraction));
+  assert_eq!(fs::read(resolve("tmp/parallel/broken/file0.txt"))?, b"data");
+  Ok(())
+}
+
+#[test]
+pub fn should_copy_large_files_exactly() -> Result<()> {
+  let out = resolve("tmp/large/media.asar");
+  fs::create_dir_all(resolve("tmp/large"))?;
+  let mut state: u32 = 0x1234_5678;
+  let video: Vec<u8> = (0..24 * 1024 * 1024 + 123)
+    .map(|_| {
+      state ^= state << 13;
+      state ^= state >> 17;
+      state ^= state << 5;
+      state as u8
+    })
+    .collect();
+  let mut builder = AsarBuilder::new();
+  builder.add_file("intro.txt", "intro", false)?;
+  builder.add_file("media/video.bin", video.clone(), false)?;
+  builder.add_file("media/empty.bin", vec![], false)?;
+  // exactly one integrity block
+  builder.add_file(
+    "media/block.bin",
+    FileSource::reader(std::io::Cursor::new(vec![7u8; 4 * 1024 * 1024])),
+    false,
+  )?;
+  builder.write(&out)?;
+
+  // the plain extraction can copy in the kernel, the verified one always
+  // goes through the buffered loop
+  let mut asar = AsarFile::open(&out)?;
+  asar.extract_file("media/video.bin", resolve("tmp/large/direct.bin"))?;
+  asar.set_verify_integrity(true);
+  asar.extract_file("media/video.bin", resolve("tmp/large/buffered.bin"))?;
+
+  assert!(fs::read(resolve("tmp/large/direct.bin"))? == video);
+  assert!(fs::read(resolve("tmp/large/buffered.bin"))? == video);
+  asar.set_verify_integrity(false);
+  asar.extract_file("media/empty.bin", resolve("tmp/large/empty.bin"))?;
+  assert_eq!(fs::metadata(resolve("tmp/large/empty.bin"))?.len(), 0);
+
+  // Safety: the archive is only read from here on
+  let mapped = unsafe { AsarFile::open_mmap(&out)? };
+  mapped.extract_file("media/video.bin", resolve("tmp/large/mapped.bin"))?;
+  assert!(fs::read(resolve("tmp/large/mapped.bin"))? == video);
+  assert!(mapped.read_file("media/block.bin")? == vec![7u8; 4 * 1024 * 1024]);
+  assert!(mapped.verify()?.is_empty());
+  Ok(())
+}
+
+#[test]
+pub fn should_pack_the_same_archive_in_parallel() -> Result<()> {
+  fs::create_dir_all(resolve("tmp/parallel-pack"))?;
+  for threads in [0, 4] {
+    let out = resolve(format!("tmp/parallel-pack/packthis-{}.asar", threads));
+    let mut options = CreateOptions::new();
+    options.threads = threads;
+    create_package_with_options(resolve("tests/input/packthis"), &out, &options)?;
+    assert!(comp_file(&out, resolve("tests/expected/packthis.asar"))?);
+
+    let out = resolve(format!("tmp/parallel-pack/unpacked-{}.asar", threads));
+    options.unpack_dir = Some("**".to_owned());
+    create_package_with_options(resolve("tests/input/packthis"), &out, &options)?;
+    assert!(comp_file(
+      &out,
+      resolve("tests/expected/packthis-all-unpacked.asar")
+    )?);
+  }
+  Ok(())
+}
+
+#[test]
+pub fn should_transform_files_with_hooks() -> Result<()> {
+  let out = resolve("tmp/hooks/packthis.asar");
+  let seen = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+  let mut options = CreateOptions::new();
+  options.unpack = Some("*.png".to_owned());
+  let suffix = String::from(" (transformed)");
+  let log = seen.clone();
+  options
+    .transforms
+    .push(Box::new(move |context: &TransformContext| {
+      log
+        .lock()
+        .unwrap()
+        .push((context.path.to_owned(), context.unpacked));
+      Ok(match context.path {
+        "dir1/file1.txt" => {
+          let suffix = suffix.clone();
+          Some(TransformOutput::adapter(move |content| {
+            Box::new(content.chain(std::io::Cursor::new(suffix)))
+          }))
+        }
+        "dir2/file2.png" => Some(TransformOutput::Bytes(b"not a png".to_vec())),
+        _ => None,
+      })
+    }));
+  options
+    .transforms
+    .push(Box::new(|context: &TransformContext| {
+      Ok(match context.path {
+        "dir1/file1.txt" => Some(TransformOutput::adapter(|mut content| {
+          let mut text = String::new();
+          content.read_to_string(&mut text).unwrap();
+          Box::new(std::io::Cursor::new(text.to_uppercase()))
+        })),
+        _ => None,
+      })
+    }));
+  create_package_with_options(resolve("tests/input/packthis"), &out, &options)?;
+
+  let seen = seen.lock().unwrap().clone();
+  assert!(seen.contains(&("dir1/file1.txt".to_owned(), false)));
+  assert!(seen.contains(&("dir2/file2.png".to_owned(), true)));
+
+  let asar = AsarFile::open(&out)?;
+  let expected = fs::read_to_string(resolve("tests/input/packthis/dir1/file1.txt"))?;
+  assert_eq!(
+    asar.read_file("dir1/file1.txt")?,
+    (expected + " (transformed)").to_uppercase().into_bytes()
+  );
+  assert_eq!(
+    fs::read(resolve("tmp/hooks/packthis.asar.unpacked/dir2/file2.png"))?,
+    b"not a png"
+  );
+  assert!(asar.verify()?.is_empty());
+
+  let mut options = CreateOptions::new();
+  options
+    .transforms
+    .push(Box::new(|context: &TransformContext| {
+      if context.path == "file0.txt" {
+        return Err(std::io::Error::other("no thanks"));
+      }
+      Ok(None)
+    }));
+  let err = create_package_with_options(resolve("tests/input/packthis"), &out, &options)
+    .err()
+    .unwrap();
+  assert!(matches!(err.status(), error::ErrorStatus::Transform));
+  assert!(err.to_string().contains("file0.txt"));
+  Ok(())
+}
+
+#[cfg(not(target_os = "windows"))]
+#[test]
+pub fn should_transform_files_with_commands() -> Result<()> {
+  let out = resolve("tmp/commands/packthis.asar");
+  let mut options = CreateOptions::new();
+  options
+    .transforms
+    .push(command_transform("*.txt", "tr a-z A-Z")?);
+  options.transforms.push(command_transform("dir1/*", "rev")?);
+  create_package_with_options(resolve("tests/input/packthis"), &out, &options)?;
+
+  let asar = AsarFile::open(&out)?;
+  assert_eq!(asar.read_file("file0.txt")?, b"FILE0 CONTENT");
+  assert_eq!(asar.read_file("dir1/file1.txt")?, b".ENO ELIF");
+  assert_eq!(
+    asar.read_file("dir2/file2.png")?,
+    fs::read(resolve("tests/input/packthis/dir2/file2.png"))?
+  );
+  assert!(asar.verify()?.is_empty());
+
+  let mut options = CreateOptions::new();
+  options
+    .transforms
+    .push(command_transform("*.png", "exit 3")?);
+  let err = create_package_with_options(resolve("tests/input/packthis"), &out, &options)
+    .err()
+    .unwrap();
+  assert!(matches!(err.status(), error::ErrorStatus::Transform));
+  assert!(err.to_string().contains("dir2/file2.png"));
+
+  let err = command_transform("[", "cat").err().unwrap();
+  assert!(matches!(err.status(), error::ErrorStatus::Pattern));
+  Ok(())
+}
+
+#[test]
+pub fn should_apply_builtin_transforms() -> Result<()> {
+  let input = resolve("tmp/builtins/input");
+  let _ = fs::remove_dir_all(&input);
+  fs::create_dir_all(input.join("lib"))?;
+  fs::write(
+    input.join("package.json"),
+    "{\r\n  \"name\": \"app\",\r\n  \"scripts\": { \"start\": \"electron .\" },\r\n  \"main\": \"lib/index.js\",\r\n  \"devDependencies\": { \"electron\": \"1.0.0\" }\r\n}\r\n",
+  )?;
+  fs::write(
+    input.join("lib/config.json"),
+    "{\n  \"title\": \"a \\\" b  c\",\n  \"sizes\": [ 1, 2.50 ]\n}\n",
+  )?;
+  fs::write(
+    input.join("lib/index.js"),
+    "console.log(1);\r\n//# sourceMappingURL=index.js.map\r\n",
+  )?;
+  fs::write(
+    input.join("lib/inline.js"),
+    "const url = '\\n//# sourceMappingURL=a.map';\n//# sourceMappingURL=x.map\nrun(url);\n",
+  )?;
+  fs::write(
+    input.join("lib/index.js.map"),
+    "{\"version\":3,\"sources\":[\"index.ts\"],\"mappings\":\"AAAA\"}",
+  )?;
+  fs::write(input.join("lib/level.map"), "#####\n#...#\n#####\n")?;
+  fs::write(input.join("lib/image.bin"), b"\r\n\r\n")?;
+
+  assert_eq!(
+    BuiltinTransform::from_name("crlf-to-lf"),
+    Some(BuiltinTransform::NormalizeLineEndings)
+  );
+  for builtin in BuiltinTransform::ALL {
+    assert_eq!(BuiltinTransform::from_name(builtin.name()), Some(builtin));
+  }
+
+  let pack = |name: &str, builtins: &[BuiltinTransform]| -> Result<AsarFile> {
+    let out = resolve("tmp/builtins").join(name);
+    let mut options = CreateOptions::new();
+    options.builtins = builtins.to_vec();
+    create_package_with_options(&input, &out, &options)?;
+    let asar = AsarFile::open(&out)?;
+    assert!(asar.verify()?.is_empty());
+    Ok(asar)
+  };
+
+  let asar = pack("minify.asar", &[BuiltinTransform::MinifyJson])?;
+  assert_eq!(
+    asar.read_file("lib/config.json")?,
+    b"{\"title\":\"a \\\" b  c\",\"sizes\":[1,2.50]}"
+  );
+  assert_eq!(
+    asar.read_file("package.json")?,
+    b"{\"name\":\"app\",\"scripts\":{\"start\":\"electron .\"},\"main\":\"lib/index.js\",\"devDependencies\":{\"electron\":\"1.0.0\"}}"
+  );
+
+  let asar = pack("crlf.asar", &[BuiltinTransform::NormalizeLineEndings])?;
+  assert_eq!(
+    asar.read_file("lib/index.js")?,
+    b"console.log(1);\n//# sourceMappingURL=index.js.map\n"
+  );
+  assert_eq!(asar.read_file("lib/image.bin")?, b"\r\n\r\n");
+
+  let asar = pack("source-maps.asar", &[BuiltinTransform::StripSourceMaps])?;
+  assert_eq!(asar.read_file("lib/index.js")?, b"console.log(1);\r\n");
+  assert_eq!(
+    asar.read_file("lib/inline.js")?,
+    fs::read(input.join("lib/inline.js"))?
+  );
+  assert!(asar.stat_file("lib/index.js.map", None).is_err());
+  assert_eq!(asar.read_file("lib/level.map")?, b"#####\n#...#\n#####\n");
+
+  let asar = pack("package.asar", &[BuiltinTransform::StripPackageJson])?;
+  assert_eq!(
+    asar.read_file("package.json")?,
+    b"{\r\n  \"name\": \"app\",\r\n  \"main\": \"lib/index.js\"\r\n}\r\n"
+  );
+
+  let asar = pack("all.asar", &BuiltinTransform::ALL)?;
+  assert_eq!(
+    asar.read_file("package.json")?,
+    b"{\"name\":\"app\",\"main\":\"lib/index.js\"}"
+  );
+  assert_eq!(asar.read_file("lib/index.js")?, b"console.log(1);\n");
+
+  // files that are not JSON after all are packed as they are
+  fs::write(input.join("lib/broken.json"), "{ \"a\": ")?;
+  fs::write(input.join("package.json"), "{ \"scripts\": ")?;
+  let asar = pack("broken.asar", &BuiltinTransform::ALL)?;
+  assert_eq!(asar.read_file("lib/broken.json")?, b"{ \"a\": ");
+  assert_eq!(asar.read_file("package.json")?, b"{ \"scripts\": ");
+  Ok(())
+}
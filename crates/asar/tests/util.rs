@@ -2,6 +2,7 @@ use sha2::{Digest, Sha256};
 use std::path::{Path, PathBuf};
 
 use anyhow::Result;
+use chromium_pickle::Pickle;
 use dircmp::Comparison;
 
 pub fn resolve<T: AsRef<Path>>(p: T) -> PathBuf {
@@ -23,3 +24,21 @@ pub fn comp_dir<T: AsRef<Path>, U: AsRef<Path>>(a: T, b: U) -> Result<bool> {
   let diff = cmp.compare(a.as_ref(), b.as_ref())?;
   Ok(diff.is_empty())
 }
+
+/// Writes an archive with a hand-written header, bypassing every check done
+/// when packing.
+pub fn write_raw_archive<T: AsRef<Path>>(archive: T, header: &str, data: &[u8]) -> Result<()> {
+  let mut header_pickle = Pickle::new();
+  header_pickle.write_string(header);
+  let header_buf = header_pickle.to_vec();
+  let mut size_pickle = Pickle::new();
+  size_pickle.write_uint32(header_buf.len() as u32);
+  if let Some(dir) = archive.as_ref().parent() {
+    std::fs::create_dir_all(dir)?;
+  }
+  std::fs::write(
+    archive,
+    [size_pickle.to_vec(), header_buf, data.to_vec()].concat(),
+  )?;
+  Ok(())
+}
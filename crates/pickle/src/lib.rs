@@ -34,72 +34,60 @@ impl<'a> PickleIterator<'a> {
     }
   }
 
-  pub fn read_bool(&mut self) -> bool {
-    let value = self.read_int32();
-    value != 0
+  pub fn read_bool(&mut self) -> Option<bool> {
+    self.read_int32().map(|value| value != 0)
   }
 
-  pub fn read_int32(&mut self) -> i32 {
-    let read_payload_offset = self.get_read_payload_offset_and_advance(SIZE_INT32);
-    let mut buf = [0u8; SIZE_INT32];
-    buf.copy_from_slice(&self.payload[read_payload_offset..read_payload_offset + SIZE_INT32]);
-    i32::from_le_bytes(buf)
+  pub fn read_int32(&mut self) -> Option<i32> {
+    self.read_bytes::<SIZE_INT32>().map(i32::from_le_bytes)
   }
 
-  pub fn read_uint32(&mut self) -> u32 {
-    let read_payload_offset = self.get_read_payload_offset_and_advance(SIZE_UINT32);
-    let mut buf = [0u8; SIZE_UINT32];
-    buf.copy_from_slice(&self.payload[read_payload_offset..read_payload_offset + SIZE_UINT32]);
-    u32::from_le_bytes(buf)
+  pub fn read_uint32(&mut self) -> Option<u32> {
+    self.read_bytes::<SIZE_UINT32>().map(u32::from_le_bytes)
   }
 
-  pub fn read_int64(&mut self) -> i64 {
-    let read_payload_offset = self.get_read_payload_offset_and_advance(SIZE_INT64);
-    let mut buf = [0u8; SIZE_INT64];
-    buf.copy_from_slice(&self.payload[read_payload_offset..read_payload_offset + SIZE_INT64]);
-    i64::from_le_bytes(buf)
+  pub fn read_int64(&mut self) -> Option<i64> {
+    self.read_bytes::<SIZE_INT64>().map(i64::from_le_bytes)
   }
 
-  pub fn read_uint64(&mut self) -> u64 {
-    let read_payload_offset = self.get_read_payload_offset_and_advance(SIZE_UINT64);
-    let mut buf = [0u8; SIZE_UINT64];
-    buf.copy_from_slice(&self.payload[read_payload_offset..read_payload_offset + SIZE_UINT64]);
-    u64::from_le_bytes(buf)
+  pub fn read_uint64(&mut self) -> Option<u64> {
+    self.read_bytes::<SIZE_UINT64>().map(u64::from_le_bytes)
   }
 
-  pub fn read_float(&mut self) -> f32 {
-    let read_payload_offset = self.get_read_payload_offset_and_advance(SIZE_FLOAT);
-    let mut buf = [0u8; SIZE_FLOAT];
-    buf.copy_from_slice(&self.payload[read_payload_offset..read_payload_offset + SIZE_FLOAT]);
-    f32::from_le_bytes(buf)
+  pub fn read_float(&mut self) -> Option<f32> {
+    self.read_bytes::<SIZE_FLOAT>().map(f32::from_le_bytes)
   }
 
-  pub fn read_double(&mut self) -> f64 {
-    let read_payload_offset = self.get_read_payload_offset_and_advance(SIZE_DOUBLE);
-    let mut buf = [0u8; SIZE_DOUBLE];
-    buf.copy_from_slice(&self.payload[read_payload_offset..read_payload_offset + SIZE_DOUBLE]);
-    f64::from_le_bytes(buf)
+  pub fn read_double(&mut self) -> Option<f64> {
+    self.read_bytes::<SIZE_DOUBLE>().map(f64::from_le_bytes)
   }
 
-  pub fn read_string(&mut self) -> String {
-    let length = self.read_int32() as usize;
-    let read_payload_offset = self.get_read_payload_offset_and_advance(length);
-    unsafe {
-      String::from_utf8_unchecked(
-        self.payload[read_payload_offset..read_payload_offset + length].to_vec(),
-      )
-    }
+  /// Reads a length-prefixed string. Returns `None` if the data is truncated
+  /// or is not valid UTF-8.
+  pub fn read_string(&mut self) -> Option<String> {
+    let length = usize::try_from(self.read_int32()?).ok()?;
+    let read_payload_offset = self.get_read_payload_offset_and_advance(length)?;
+    let bytes = &self.payload[read_payload_offset..read_payload_offset + length];
+    String::from_utf8(bytes.to_vec()).ok()
+  }
+
+  fn read_bytes<const N: usize>(&mut self) -> Option<[u8; N]> {
+    let read_payload_offset = self.get_read_payload_offset_and_advance(N)?;
+    let mut buf = [0u8; N];
+    buf.copy_from_slice(&self.payload[read_payload_offset..read_payload_offset + N]);
+    Some(buf)
   }
 
-  fn get_read_payload_offset_and_advance(&mut self, length: usize) -> usize {
-    assert!(
-      length <= self.end_index - self.read_index,
-      "chromium_pickle: Failed to read data with length of {}",
-      length
-    );
+  fn get_read_payload_offset_and_advance(&mut self, length: usize) -> Option<usize> {
+    if length > self.end_index - self.read_index {
+      return None;
+    }
     let read_payload_offset = self.payload_offset + self.read_index;
+    if read_payload_offset + length > self.payload.len() {
+      return None;
+    }
     self.advance(length);
-    read_payload_offset
+    Some(read_payload_offset)
   }
 
   fn advance(&mut self, size: usize) {
@@ -149,14 +137,13 @@ impl Pickle {
     if len >= UINT32_SIZE {
       payload_size_buffer.copy_from_slice(&buffer[0..UINT32_SIZE]);
     } else {
-      for i in 0..len {
-        payload_size_buffer[i] = buffer[i];
-      }
+      payload_size_buffer[0..len].copy_from_slice(&buffer[0..len]);
     }
 
+    let payload_size = u32::from_le_bytes(payload_size_buffer) as usize;
     let mut pickle = Pickle {
       header: buffer.to_vec(),
-      header_size: len - u32::from_le_bytes(payload_size_buffer) as usize,
+      header_size: len.checked_sub(payload_size).unwrap_or(usize::MAX),
       capacity_after_header: CAPACITY_READ_ONLY,
       write_offset: 0,
     };
@@ -174,11 +161,11 @@ impl Pickle {
 
   pub fn to_vec(&self) -> Vec<u8> {
     let end = self.header_size + self.get_payload_size();
-    self.header[0..end].to_vec()
+    self.header[0..std::cmp::min(end, self.header.len())].to_vec()
   }
 
-  pub fn create_iterator(&self) -> PickleIterator {
-    PickleIterator::new(&self)
+  pub fn create_iterator(&self) -> PickleIterator<'_> {
+    PickleIterator::new(self)
   }
 
   pub fn write_bool(&mut self, value: bool) {
@@ -213,11 +200,11 @@ impl Pickle {
     let bytes = value.as_bytes();
     let length = bytes.len();
     self.write_int32(length as i32);
-    self.write_bytes(&bytes, length);
+    self.write_bytes(bytes, length);
   }
 
   fn write_bytes(&mut self, data: &[u8], length: usize) {
-    let data_length = align_int(length, SIZE_UINT32 as usize);
+    let data_length = align_int(length, SIZE_UINT32);
     let new_size = self.write_offset + data_length;
     if new_size as u64 > self.capacity_after_header {
       let double_cap = self.capacity_after_header * 2;
@@ -244,11 +231,12 @@ impl Pickle {
   }
 
   pub fn get_payload_size(&self) -> usize {
-    u32::from_le_bytes(
-      self.header[0..4]
-        .try_into()
-        .unwrap_or_else(|_| [0, 0, 0, 0]),
-    ) as usize
+    self
+      .header
+      .get(0..4)
+      .and_then(|bytes| bytes.try_into().ok())
+      .map(u32::from_le_bytes)
+      .unwrap_or(0) as usize
   }
 
   pub fn set_payload_size(&mut self, payload_size: usize) {
@@ -272,7 +260,40 @@ mod tests {
     write.write_string("女の子.txt");
     let read = Pickle::from_vec(write.to_vec());
     let mut it = read.create_iterator();
-    assert_eq!(it.read_string(), "女の子.txt");
+    assert_eq!(it.read_string().as_deref(), Some("女の子.txt"));
     Ok(())
   }
+
+  #[test]
+  fn rejects_truncated_data() {
+    let mut write = Pickle::default();
+    write.write_uint32(10);
+    write.write_string("hello");
+    let buffer = write.to_vec();
+
+    let read = Pickle::from_slice(&buffer[0..buffer.len() - 4]);
+    let mut it = read.create_iterator();
+    assert_eq!(it.read_uint32(), None);
+
+    let read = Pickle::from_slice(&buffer[0..2]);
+    assert_eq!(read.create_iterator().read_uint32(), None);
+
+    let mut write = Pickle::default();
+    write.write_int32(100);
+    let read = Pickle::from_vec(write.to_vec());
+    assert_eq!(read.create_iterator().read_string(), None);
+
+    let read = Pickle::from_vec(vec![]);
+    assert_eq!(read.create_iterator().read_bool(), None);
+  }
+
+  #[test]
+  fn rejects_invalid_utf8() {
+    let mut write = Pickle::default();
+    write.write_int32(2);
+    write.write_uint32(u32::from_le_bytes([0xff, 0xfe, 0, 0]));
+    let read = Pickle::from_vec(write.to_vec());
+    let mut it = read.create_iterator();
+    assert_eq!(it.read_string(), None);
+  }
 }